@@ -0,0 +1,83 @@
+use crate::timebase::{GetDataResponse, TimebaseClient};
+use actix_web::web;
+use chrono::{DateTime, TimeDelta};
+use std::time::Duration;
+
+mod datasets;
+mod error;
+mod events;
+mod live;
+mod series;
+mod snapshot;
+
+pub use error::ApiError;
+pub use live::LiveHub;
+
+/// State shared by all request handlers.
+pub struct AppState {
+    pub client: TimebaseClient,
+    pub live: LiveHub,
+}
+
+impl AppState {
+    /// Live subscriptions poll the historian every `poll_interval`.
+    pub fn new(client: TimebaseClient, poll_interval: Duration) -> Self {
+        Self {
+            live: LiveHub::new(client.clone(), poll_interval),
+            client,
+        }
+    }
+}
+
+/// Registers the routes of the REST API under `/api`.
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/api")
+            .route("/datasets", web::get().to(datasets::list_datasets))
+            .route("/datasets/{dataset}/tags", web::get().to(datasets::list_tags))
+            .route("/datasets/{dataset}/tags/{tag}", web::get().to(datasets::get_tag))
+            .route("/datasets/{dataset}/data", web::get().to(series::get_data))
+            .route("/datasets/{dataset}/export", web::get().to(series::export_csv))
+            .route("/datasets/{dataset}/events", web::post().to(events::detect_events))
+            .route("/datasets/{dataset}/live", web::get().to(live::live))
+            .route("/datasets/{dataset}/snapshot", web::get().to(snapshot::get_snapshot)),
+    );
+}
+
+// Splits a comma-separated `tags` parameter
+fn tag_list(tags: &str) -> Result<Vec<&str>, ApiError> {
+    let tags: Vec<&str> = tags.split(',').map(str::trim).filter(|t| !t.is_empty()).collect();
+
+    if tags.is_empty() {
+        return Err(ApiError::BadRequest("at least one tag is required".into()));
+    }
+
+    Ok(tags)
+}
+
+// Fetches the tags over a range given as RFC 3339 times or relative expressions such as `*-8h`.
+// Absolute ranges longer than a day are fetched in daily chunks.
+async fn fetch(client: &TimebaseClient, dataset: &str, tags: &[&str], start: &str, end: &str) -> Result<GetDataResponse, ApiError> {
+    let mut request = client.get_data(dataset);
+    for tag in tags {
+        request = request.tag_name(tag);
+    }
+
+    request = match start.starts_with('*') {
+        true => request.relative_start(start),
+        false => request.start_iso(start)?,
+    };
+
+    request = match end.starts_with('*') {
+        true => request.relative_end(end),
+        false => request.end_iso(end)?,
+    };
+
+    if let (Ok(start), Ok(end)) = (DateTime::parse_from_rfc3339(start), DateTime::parse_from_rfc3339(end))
+        && end - start > TimeDelta::days(1)
+    {
+        request = request.chunk_duration(TimeDelta::days(1));
+    }
+
+    Ok(request.build()?.send().await?)
+}
@@ -0,0 +1,42 @@
+use super::{ApiError, AppState};
+use crate::timebase::Dataset;
+use crate::timeseries::Tag;
+use actix_web::web;
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+pub(super) struct TagQuery {
+    /// Wildcard pattern such as `131-F?-*`.
+    filter: Option<String>,
+    prefix: Option<String>,
+}
+
+/// `GET /api/datasets`
+pub(super) async fn list_datasets(state: web::Data<AppState>) -> Result<web::Json<Vec<Dataset>>, ApiError> {
+    Ok(web::Json(state.client.list_datasets().await?))
+}
+
+/// `GET /api/datasets/{dataset}/tags?filter=&prefix=`
+pub(super) async fn list_tags(state: web::Data<AppState>, dataset: web::Path<String>, query: web::Query<TagQuery>) -> Result<web::Json<Vec<Tag>>, ApiError> {
+    let mut request = state.client.list_tags(&dataset);
+
+    if let Some(filter) = &query.filter {
+        request = request.filter(filter);
+    }
+
+    if let Some(prefix) = &query.prefix {
+        request = request.prefix(prefix);
+    }
+
+    let tags = request.send().await?;
+
+    Ok(web::Json(tags.iter().map(Tag::from).collect()))
+}
+
+/// `GET /api/datasets/{dataset}/tags/{tag}`
+pub(super) async fn get_tag(state: web::Data<AppState>, path: web::Path<(String, String)>) -> Result<web::Json<Tag>, ApiError> {
+    let (dataset, tag) = path.into_inner();
+    let tag = state.client.get_tag_info(&dataset, &tag).await?;
+
+    Ok(web::Json(Tag::from(&tag)))
+}
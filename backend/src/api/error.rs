@@ -0,0 +1,43 @@
+use crate::timebase::TimebaseError;
+use crate::timeseries::EventError;
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError};
+use serde_json::json;
+use thiserror::Error;
+
+/// Errors returned by the REST API, sent to the client as `{"error": "..."}`.
+#[derive(Error, Debug)]
+pub enum ApiError {
+    /// A query parameter or request body could not be used.
+    #[error("{0}")]
+    BadRequest(String),
+
+    #[error(transparent)]
+    Timebase(#[from] TimebaseError),
+
+    #[error(transparent)]
+    Event(#[from] EventError),
+
+    #[error("failed to write CSV: {0}")]
+    Export(#[from] csv::Error),
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::BadRequest(_) | ApiError::Event(_) => StatusCode::BAD_REQUEST,
+            ApiError::Timebase(e) => match e {
+                TimebaseError::UnknownDataset(_) | TimebaseError::UnknownTag { .. } => StatusCode::NOT_FOUND,
+                TimebaseError::InvalidTime(_) => StatusCode::BAD_REQUEST,
+                _ if e.is_timeout() => StatusCode::GATEWAY_TIMEOUT,
+                TimebaseError::Transport(_) | TimebaseError::Status { .. } | TimebaseError::Decode { .. } => StatusCode::BAD_GATEWAY,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            },
+            ApiError::Export(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code()).json(json!({ "error": self.to_string() }))
+    }
+}
@@ -0,0 +1,43 @@
+use super::{fetch, ApiError, AppState};
+use crate::timeseries::{DataSeries, EventDefinition, EventSeries};
+use actix_web::web;
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+pub(super) struct EventRequest {
+    start: String,
+    end: String,
+    definition: EventDefinition,
+}
+
+/// `POST /api/datasets/{dataset}/events`
+///
+/// Detects events over a range, e.g. batches from a batch id tag:
+///
+/// ```json
+/// {
+///   "start": "*-7d",
+///   "end": "*",
+///   "definition": {
+///     "name": "FL001 Batch",
+///     "start": { "type": "value_change", "tag": "FL001.BatchId" },
+///     "attributes": [{ "name": "Product", "tag": "FL001.Product", "at": "start" }]
+///   }
+/// }
+/// ```
+pub(super) async fn detect_events(state: web::Data<AppState>, dataset: web::Path<String>, request: web::Json<EventRequest>) -> Result<web::Json<EventSeries>, ApiError> {
+    let definition = &request.definition;
+
+    let mut tags: Vec<&str> = std::iter::once(definition.start.tag())
+        .chain(definition.end.as_ref().map(|end| end.tag()))
+        .chain(definition.attributes.iter().map(|a| a.tag.as_str()))
+        .collect();
+    tags.sort_unstable();
+    tags.dedup();
+
+    let response = fetch(&state.client, &dataset, &tags, &request.start, &request.end).await?;
+    let time_series = response.time_series();
+    let series: Vec<&DataSeries> = time_series.iter().collect();
+
+    Ok(web::Json(definition.detect(&series)?))
+}
@@ -0,0 +1,231 @@
+use super::{tag_list, ApiError, AppState};
+use crate::timebase::{GetDataResponse, TimebaseClient, TimebaseError};
+use crate::timeseries::DataPoint;
+use actix_web::{rt, web, HttpResponse};
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use futures::future;
+use futures::stream::{self, StreamExt};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tracing::{debug, info, warn};
+
+// Points buffered per subscriber before a slow browser starts missing updates
+const CHANNEL_CAPACITY: usize = 1024;
+
+// Comment lines keep idle connections open through proxies
+const KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+type Pollers = Arc<Mutex<HashMap<String, Arc<Poller>>>>;
+
+/// Polls the historian for new points of watched tags and fans them out to subscribers.
+///
+/// Each dataset has at most one polling task, whatever the number of clients: every tick it
+/// fetches all watched tags of the dataset in one request, starting from the oldest last
+/// timestamp seen but no earlier than the end of the previous poll, and forwards only points
+/// newer than each tag's last timestamp. The task stops once no subscriber is left.
+#[derive(Clone)]
+pub struct LiveHub {
+    client: TimebaseClient,
+    interval: Duration,
+    pollers: Pollers,
+}
+
+// Watches are keyed by lowercase tag name: Timebase matches names case-insensitively and
+// returns them in its own case, whatever case they were requested in
+#[derive(Default)]
+struct Poller {
+    tags: Mutex<HashMap<String, Watch>>,
+}
+
+struct Watch {
+    /// The name as first subscribed, used in requests.
+    name: String,
+    sender: broadcast::Sender<DataPoint>,
+    last: Option<DateTime<Utc>>,
+}
+
+impl LiveHub {
+    pub fn new(client: TimebaseClient, interval: Duration) -> Self {
+        Self {
+            client,
+            interval,
+            pollers: Default::default(),
+        }
+    }
+
+    /// Subscribes to new points of a tag. The receiver closes if the tag turns out not to exist.
+    pub fn subscribe(&self, dataset: &str, tag: &str) -> broadcast::Receiver<DataPoint> {
+        let mut pollers = self.pollers.lock().unwrap();
+
+        let poller = match pollers.get(dataset) {
+            Some(poller) => poller.clone(),
+            None => {
+                let poller = Arc::new(Poller::default());
+                pollers.insert(dataset.to_string(), poller.clone());
+                rt::spawn(self.clone().poll(dataset.to_string(), poller.clone()));
+                poller
+            }
+        };
+
+        let mut tags = poller.tags.lock().unwrap();
+        let watch = tags.entry(tag.to_lowercase()).or_insert_with(|| Watch {
+            name: tag.to_string(),
+            sender: broadcast::channel(CHANNEL_CAPACITY).0,
+            last: None,
+        });
+
+        watch.sender.subscribe()
+    }
+
+    async fn poll(self, dataset: String, poller: Arc<Poller>) {
+        info!(%dataset, "live polling started");
+
+        // End of the last successful poll, so that a tag without new points does not pin the
+        // start of every later request
+        let mut cursor = None;
+
+        loop {
+            tokio::time::sleep(self.interval).await;
+
+            let Some(watched) = self.watched(&dataset, &poller) else {
+                break;
+            };
+
+            match self.fetch(&dataset, &watched, cursor).await {
+                Ok(response) => {
+                    cursor = Some(response.end);
+                    let mut tags = poller.tags.lock().unwrap();
+
+                    for series in response.time_series() {
+                        let Some(watch) = tags.get_mut(&series.tag.name.to_lowercase()) else {
+                            continue;
+                        };
+
+                        for point in series.data {
+                            if watch.last.is_some_and(|last| point.timestamp <= last) {
+                                continue;
+                            }
+                            watch.last = Some(point.timestamp);
+                            // Fails only when every subscriber has gone, which the next tick handles
+                            let _ = watch.sender.send(point);
+                        }
+                    }
+                }
+                Err(TimebaseError::UnknownTag { tag, .. }) => {
+                    // Dropping the sender ends the streams of the tag's subscribers
+                    warn!(%dataset, %tag, "unknown tag dropped from live polling");
+                    poller.tags.lock().unwrap().remove(&tag.to_lowercase());
+                }
+                Err(e) => warn!(%dataset, error = %e, "live poll failed"),
+            }
+        }
+
+        info!(%dataset, "live polling stopped");
+    }
+
+    // Forgets tags without subscribers and returns the remaining ones with their last
+    // timestamps, or `None` after removing the dataset once nothing is watched
+    fn watched(&self, dataset: &str, poller: &Poller) -> Option<Vec<(String, Option<DateTime<Utc>>)>> {
+        let mut pollers = self.pollers.lock().unwrap();
+        let mut tags = poller.tags.lock().unwrap();
+
+        tags.retain(|_, watch| watch.sender.receiver_count() > 0);
+
+        if tags.is_empty() {
+            pollers.remove(dataset);
+            return None;
+        }
+
+        Some(tags.values().map(|watch| (watch.name.clone(), watch.last)).collect())
+    }
+
+    async fn fetch(&self, dataset: &str, watched: &[(String, Option<DateTime<Utc>>)], cursor: Option<DateTime<Utc>>) -> Result<GetDataResponse, TimebaseError> {
+        let mut request = self.client.get_data(dataset);
+        for (tag, _) in watched {
+            request = request.tag_name(tag);
+        }
+
+        // Tags seen before resume from the oldest last timestamp, but not before the previous
+        // poll ended; the first poll looks back one interval
+        let lookback = format!("*-{}ms", self.interval.as_millis());
+        let oldest = watched.iter().filter_map(|(_, last)| *last).min();
+        request = match oldest.max(cursor) {
+            Some(start) => request.start(start),
+            None => request.relative_start(&lookback),
+        };
+
+        let response = request.relative_end("*").build()?.send().await?;
+        debug!(dataset, tags = watched.len(), "live poll");
+
+        Ok(response)
+    }
+}
+
+/// A new point of a tag as sent to the browser.
+#[derive(Serialize)]
+struct LiveUpdate<'a> {
+    tag: &'a str,
+    #[serde(flatten)]
+    point: &'a DataPoint,
+}
+
+#[derive(serde::Deserialize)]
+pub(super) struct LiveQuery {
+    /// Comma-separated tag names.
+    tags: String,
+}
+
+/// `GET /api/datasets/{dataset}/live?tags=`
+///
+/// Streams new points as server-sent events, one `point` event per point:
+///
+/// ```text
+/// event: point
+/// data: {"tag":"131-FT-001.PV","timestamp":"...","value":12.5,"quality":192}
+/// ```
+pub(super) async fn live(state: web::Data<AppState>, dataset: web::Path<String>, query: web::Query<LiveQuery>) -> Result<HttpResponse, ApiError> {
+    let mut tags = tag_list(&query.tags)?;
+
+    // A tag listed twice, in any case, would share one watch and stream every point twice
+    let mut seen = HashSet::new();
+    tags.retain(|tag| seen.insert(tag.to_lowercase()));
+
+    let updates = stream::select_all(tags.into_iter().map(|tag| {
+        let receiver = state.live.subscribe(&dataset, tag);
+
+        stream::unfold((tag.to_string(), receiver), |(tag, mut receiver)| async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(point) => {
+                        let update = serde_json::to_string(&LiveUpdate { tag: &tag, point: &point }).ok()?;
+                        return Some((format!("event: point\ndata: {}\n\n", update), (tag, receiver)));
+                    }
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        warn!(%tag, missed, "live subscriber lagging");
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        })
+        .boxed_local()
+    }));
+
+    let keep_alive = stream::unfold((), |_| async {
+        tokio::time::sleep(KEEP_ALIVE).await;
+        Some((": keep-alive\n\n".to_string(), ()))
+    });
+
+    // The response ends with the updates, e.g. once every requested tag proved unknown
+    let events = stream::select(updates.map(Some).chain(stream::once(async { None })), keep_alive.map(Some))
+        .scan((), |_, event| future::ready(event))
+        .map(|event| Ok::<_, actix_web::Error>(Bytes::from(event)));
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .streaming(events))
+}
@@ -0,0 +1,146 @@
+use super::{fetch, tag_list, ApiError, AppState};
+use crate::timeseries::{self, Aggregate, AlignOptions, CsvOptions, DataSeries, Fill, ResampleMethod};
+use actix_web::{web, HttpResponse};
+use chrono::{DateTime, TimeDelta};
+use chrono_tz::Tz;
+use serde::Deserialize;
+
+// Upper bound on the points a resampled series may have
+const MAX_POINTS: i64 = 100_000;
+
+#[derive(Deserialize)]
+pub(super) struct DataQuery {
+    /// Comma-separated tag names.
+    tags: String,
+    start: String,
+    end: String,
+    /// Resampling interval such as `15m`. Raw data is returned without one.
+    interval: Option<String>,
+    /// `step`, `linear` or an aggregate such as `average`. Defaults to `step`.
+    method: Option<String>,
+}
+
+/// `GET /api/datasets/{dataset}/data?tags=&start=&end=&interval=&method=`
+///
+/// Returns one series per tag: raw, resampled at grid times, or aggregated per interval.
+pub(super) async fn get_data(state: web::Data<AppState>, dataset: web::Path<String>, query: web::Query<DataQuery>) -> Result<web::Json<Vec<DataSeries>>, ApiError> {
+    Ok(web::Json(load(&state, &dataset, &query).await?))
+}
+
+#[derive(Deserialize)]
+pub(super) struct ExportQuery {
+    #[serde(flatten)]
+    data: DataQuery,
+    /// IANA time zone of the timestamps, e.g. `America/Chicago`. Defaults to UTC.
+    timezone: Option<String>,
+    /// `strftime` pattern of the timestamps, e.g. `%Y-%m-%d %H:%M:%S`.
+    timestamp_format: Option<String>,
+    /// A single character. Defaults to `,`.
+    delimiter: Option<String>,
+}
+
+/// `GET /api/datasets/{dataset}/export?tags=&start=&end=&interval=&method=&timezone=&timestamp_format=&delimiter=`
+///
+/// Downloads the series of `/data` aligned into one CSV table, one column per tag.
+pub(super) async fn export_csv(state: web::Data<AppState>, dataset: web::Path<String>, query: web::Query<ExportQuery>) -> Result<HttpResponse, ApiError> {
+    let mut options = CsvOptions::new();
+
+    if let Some(timezone) = &query.timezone {
+        let timezone: Tz = timezone.parse().map_err(|_| ApiError::BadRequest(format!("unknown time zone \"{}\"", timezone)))?;
+        options = options.timezone(timezone);
+    }
+
+    if let Some(format) = &query.timestamp_format {
+        options = options.timestamp_format(format).map_err(|e| ApiError::BadRequest(e.to_string()))?;
+    }
+
+    if let Some(delimiter) = &query.delimiter {
+        match delimiter.as_bytes() {
+            [delimiter] => options = options.delimiter(*delimiter),
+            _ => return Err(ApiError::BadRequest(format!("invalid delimiter \"{}\"", delimiter))),
+        }
+    }
+
+    let series = load(&state, &dataset, &query.data).await?;
+    let table = timeseries::align(&series.iter().collect::<Vec<_>>(), &AlignOptions::new(Fill::CarryForward));
+
+    let mut csv = vec![];
+    table.write_csv(&mut csv, &options)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/csv; charset=utf-8")
+        .insert_header(("Content-Disposition", format!("attachment; filename=\"{}.csv\"", dataset.replace('"', ""))))
+        .body(csv))
+}
+
+async fn load(state: &AppState, dataset: &str, query: &DataQuery) -> Result<Vec<DataSeries>, ApiError> {
+    let tags = tag_list(&query.tags)?;
+
+    // Check the parameters before going to the historian
+    let resample = match &query.interval {
+        None => None,
+        Some(interval) => Some((parse_interval(interval)?, parse_method(query.method.as_deref().unwrap_or("step"))?)),
+    };
+
+    let check_points = |span: TimeDelta, interval: TimeDelta| match span.num_milliseconds() / interval.num_milliseconds() > MAX_POINTS {
+        true => Err(ApiError::BadRequest(format!("interval {} gives more than {} points", query.interval.as_deref().unwrap_or_default(), MAX_POINTS))),
+        false => Ok(()),
+    };
+
+    // Relative ranges are only known once the historian has resolved them
+    if let Some((interval, _)) = &resample
+        && let (Ok(start), Ok(end)) = (DateTime::parse_from_rfc3339(&query.start), DateTime::parse_from_rfc3339(&query.end))
+    {
+        check_points(end - start, *interval)?;
+    }
+
+    let response = fetch(&state.client, dataset, &tags, &query.start, &query.end).await?;
+    let series = response.time_series();
+
+    let Some((interval, method)) = resample else {
+        return Ok(series);
+    };
+
+    check_points(response.end - response.start, interval)?;
+
+    Ok(series.iter().map(|s| s.resample(response.start, response.end, interval, method)).collect())
+}
+
+// Intervals are a whole number followed by ms, s, m, h or d, e.g. `15m`
+fn parse_interval(interval: &str) -> Result<TimeDelta, ApiError> {
+    let invalid = || ApiError::BadRequest(format!("invalid interval \"{}\"", interval));
+
+    let digits = interval.len() - interval.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let count: i64 = interval[..digits].parse().map_err(|_| invalid())?;
+
+    let delta = match &interval[digits..] {
+        "ms" => TimeDelta::try_milliseconds(count),
+        "s" => TimeDelta::try_seconds(count),
+        "m" => TimeDelta::try_minutes(count),
+        "h" => TimeDelta::try_hours(count),
+        "d" => TimeDelta::try_days(count),
+        _ => None,
+    };
+
+    delta.filter(|d| *d > TimeDelta::zero()).ok_or_else(invalid)
+}
+
+fn parse_method(method: &str) -> Result<ResampleMethod, ApiError> {
+    let aggregate = match method {
+        "step" => return Ok(ResampleMethod::StepHold),
+        "linear" => return Ok(ResampleMethod::Linear),
+        "min" => Aggregate::Min,
+        "max" => Aggregate::Max,
+        "first" => Aggregate::First,
+        "last" => Aggregate::Last,
+        "count" => Aggregate::Count,
+        "average" => Aggregate::Average,
+        "time_weighted_average" => Aggregate::TimeWeightedAverage,
+        "range" => Aggregate::Range,
+        "std_dev" => Aggregate::StdDev,
+        "delta" => Aggregate::Delta,
+        _ => return Err(ApiError::BadRequest(format!("unknown method \"{}\"", method))),
+    };
+
+    Ok(ResampleMethod::Aggregate(aggregate))
+}
@@ -0,0 +1,19 @@
+use super::{tag_list, ApiError, AppState};
+use crate::timebase::Snapshot;
+use actix_web::web;
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+pub(super) struct SnapshotQuery {
+    /// Comma-separated tag names.
+    tags: String,
+}
+
+/// `GET /api/datasets/{dataset}/snapshot?tags=`
+///
+/// Returns the latest point of each tag with its quality and age in seconds.
+pub(super) async fn get_snapshot(state: web::Data<AppState>, dataset: web::Path<String>, query: web::Query<SnapshotQuery>) -> Result<web::Json<Vec<Snapshot>>, ApiError> {
+    let tags = tag_list(&query.tags)?;
+
+    Ok(web::Json(state.client.get_snapshot(&dataset, &tags).await?))
+}
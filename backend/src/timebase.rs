@@ -1,11 +1,37 @@
-use chrono::{DateTime, FixedOffset, TimeZone, Utc};
-use reqwest::{Client, Url};
+use chrono::{DateTime, FixedOffset, TimeDelta, TimeZone, Utc};
+use futures::{StreamExt, TryStreamExt};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::{Client, Proxy, RequestBuilder, Response, StatusCode, Url};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::time::Duration;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{debug, info, info_span, warn, Instrument, Span};
+
+mod auth;
+mod cache;
+mod discovery;
+mod error;
+mod retry;
+mod snapshot;
+mod stream;
+mod write;
+#[cfg(test)]
+mod testing;
+
+pub use auth::{Auth, ExpiringToken, RefreshingTokenProvider, TokenProvider};
+pub use cache::{CacheEntry, CacheKey, CacheStore, DataCache, DiskStore, MemoryStore, Segment};
+pub use discovery::{Dataset, ListTagsRequestBuilder};
+pub use error::TimebaseError;
+pub use retry::{RetryEvent, RetryPolicy};
+pub use snapshot::Snapshot;
+pub use stream::DataPointStream;
+pub use write::{WriteDataRequestBuilder, WriteDataResult};
+
 // This module contains all structs and enums related to the timebase data model
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Tag {
     #[serde(rename = "n")]
     pub name: String,
@@ -26,7 +52,7 @@ pub struct Tag {
     pub data_type: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct TagData {
     #[serde(rename = "t")]
     pub timestamp: DateTime<Utc>,
@@ -70,72 +96,276 @@ pub struct GetDataResponse {
 }
 
 
+// How the body of a response is read, which decides how the client timeout applies
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Exchange {
+    /// Read whole; the request has to complete within the timeout.
+    Buffered,
+    /// Read as it arrives; the timeout bounds the wait for the headers and each body chunk.
+    Streamed,
+    /// Read whole like `Buffered`, but changes data on the server and so is only retried when
+    /// the retry policy allows it.
+    Write,
+}
+
+/// Client for the Timebase historian REST API.
+///
+/// The client owns a single `reqwest::Client`, so every request it creates shares the same
+/// connection pool and TLS sessions. Cloning a `TimebaseClient` is cheap and keeps sharing
+/// the pool.
+#[derive(Clone)]
 pub struct TimebaseClient {
     base_url: Url,
     timeout: Duration,
+    connect_timeout: Option<Duration>,
+    pool_idle_timeout: Option<Duration>,
+    pool_max_idle_per_host: Option<usize>,
+    default_headers: HeaderMap,
+    proxy: Option<Proxy>,
+    auth: Option<Auth>,
+    retry: RetryPolicy,
+    cache: Option<Arc<DataCache>>,
+    http: Client,
 }
 
 impl TimebaseClient {
     pub fn new() -> Self {
-        Self {
-            base_url: match Url::parse("http://localhost:4511") {
-                Ok(url) => url,
-                Err(_) => panic!("Invalid base URL")
-            },
-            timeout: Duration::from_secs(30)
-        }
+        Self::from_url(&match Url::parse("http://localhost:4511") {
+            Ok(url) => url,
+            Err(_) => panic!("Invalid base URL")
+        })
     }
 
     pub fn from_url(base_url: &Url) -> Self {
         Self {
             base_url: base_url.clone(),
-            timeout: Duration::from_secs(30)
+            timeout: Duration::from_secs(30),
+            connect_timeout: None,
+            pool_idle_timeout: None,
+            pool_max_idle_per_host: None,
+            default_headers: HeaderMap::new(),
+            proxy: None,
+            auth: None,
+            retry: RetryPolicy::default(),
+            cache: None,
+            http: Client::new(),
         }
     }
 
-    pub fn from_str(base_url: &str) -> Result<Self, Box<dyn std::error::Error>> {
-        Ok(Self {
-            base_url: match Url::parse(base_url) {
-                Ok(url) => url,
-                Err(_) => return Err("Invalid base URL".into())
-            },
-            timeout: Duration::from_secs(30)
-        })
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(base_url: &str) -> Result<Self, TimebaseError> {
+        match Url::parse(base_url) {
+            Ok(url) => Ok(Self::from_url(&url)),
+            Err(e) => Err(TimebaseError::InvalidUrl(format!("{}: {}", base_url, e)))
+        }
     }
 
-    pub fn from_host(host: &str) -> Result<Self, Box<dyn std::error::Error>> {
-        Ok(Self {
-            base_url: Url::parse(format!("http://{}:4511", host).as_str())?,
-            timeout: Duration::from_secs(30)
-        })
+    pub fn from_host(host: &str) -> Result<Self, TimebaseError> {
+        Self::from_str(format!("http://{}:4511", host).as_str())
     }
 
-    pub fn set_host(mut self, host: &str) -> Result<Self, Box<dyn std::error::Error>> {
+    pub fn set_host(mut self, host: &str) -> Result<Self, TimebaseError> {
         match self.base_url.set_host(Some(host)) {
             Ok(_) => Ok(self),
-            Err(_) => Err("Invalid host".into())
+            Err(e) => Err(TimebaseError::InvalidUrl(format!("Invalid host {}: {}", host, e)))
         }
     }
 
-    pub fn set_scheme(mut self, scheme: &str) -> Result<Self, Box<dyn std::error::Error>> {
+    pub fn set_scheme(mut self, scheme: &str) -> Result<Self, TimebaseError> {
         match self.base_url.set_scheme(scheme) {
             Ok(_) => Ok(self),
-            Err(_) => Err("Invalid scheme".into())
+            Err(_) => Err(TimebaseError::InvalidUrl(format!("Invalid scheme {}", scheme)))
         }
     }
 
-    pub fn set_port(mut self, port: u16) -> Result<Self, Box<dyn std::error::Error>> {
+    pub fn set_port(mut self, port: u16) -> Result<Self, TimebaseError> {
         match self.base_url.set_port(Some(port)) {
             Ok(_) => Ok(self),
-            Err(_) => Err("Invalid port".into())
+            Err(_) => Err(TimebaseError::InvalidUrl(format!("Invalid port {}", port)))
         }
     }
 
+    /// Sets the timeout applied to each request. A buffered request has to complete within
+    /// it; a streamed request only fails when no data arrives for that long.
     pub fn set_timeout(mut self, timeout: Duration) -> Self {
         self.timeout = timeout;
         self
     }
 
+    /// Sets the timeout for establishing a new connection.
+    pub fn set_connect_timeout(mut self, timeout: Duration) -> Result<Self, TimebaseError> {
+        self.connect_timeout = Some(timeout);
+        self.rebuild()
+    }
+
+    /// Sets how long idle connections are kept in the pool.
+    pub fn set_pool_idle_timeout(mut self, timeout: Duration) -> Result<Self, TimebaseError> {
+        self.pool_idle_timeout = Some(timeout);
+        self.rebuild()
+    }
+
+    /// Sets the maximum number of idle connections kept per host.
+    pub fn set_pool_max_idle_per_host(mut self, max: usize) -> Result<Self, TimebaseError> {
+        self.pool_max_idle_per_host = Some(max);
+        self.rebuild()
+    }
+
+    /// Adds a header that is sent with every request.
+    pub fn set_default_header(mut self, name: &str, value: &str) -> Result<Self, TimebaseError> {
+        let name = match HeaderName::from_bytes(name.as_bytes()) {
+            Ok(name) => name,
+            Err(_) => return Err(TimebaseError::Configuration(format!("Invalid header name {}", name)))
+        };
+        let value = match HeaderValue::from_str(value) {
+            Ok(value) => value,
+            Err(_) => return Err(TimebaseError::Configuration(format!("Invalid value for header {}", name)))
+        };
+
+        self.default_headers.insert(name, value);
+        self.rebuild()
+    }
+
+    /// Routes every request through the given HTTP/HTTPS/SOCKS proxy.
+    pub fn set_proxy(mut self, proxy_url: &str) -> Result<Self, TimebaseError> {
+        match Proxy::all(proxy_url) {
+            Ok(proxy) => self.proxy = Some(proxy),
+            Err(e) => return Err(TimebaseError::Configuration(format!("Invalid proxy {}: {}", proxy_url, e)))
+        }
+        self.rebuild()
+    }
+
+    /// Sets the credentials sent with every request.
+    pub fn set_auth(mut self, auth: Auth) -> Self {
+        self.auth = Some(auth);
+        self
+    }
+
+    /// Sets which failed requests are retried and how. Defaults to [`RetryPolicy::default`].
+    pub fn set_retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Serves data requests over absolute time ranges from a cache, fetching only the parts
+    /// it does not hold. Clones of the client share the cache.
+    pub fn set_cache(mut self, cache: DataCache) -> Self {
+        self.cache = Some(Arc::new(cache));
+        self
+    }
+
+    /// Replaces the underlying HTTP client with one configured by the caller. A later call to
+    /// a setter that rebuilds the client (headers, proxy, pool or connect timeout) discards it.
+    pub fn set_http_client(mut self, http: Client) -> Self {
+        self.http = http;
+        self
+    }
+
+    fn rebuild(mut self) -> Result<Self, TimebaseError> {
+        let mut builder = Client::builder().default_headers(self.default_headers.clone());
+
+        if let Some(timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+
+        if let Some(timeout) = self.pool_idle_timeout {
+            builder = builder.pool_idle_timeout(timeout);
+        }
+
+        if let Some(max) = self.pool_max_idle_per_host {
+            builder = builder.pool_max_idle_per_host(max);
+        }
+
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(proxy.clone());
+        }
+
+        self.http = match builder.build() {
+            Ok(http) => http,
+            Err(e) => return Err(TimebaseError::Configuration(e.to_string()))
+        };
+
+        Ok(self)
+    }
+
+    // Every request the client issues goes through here so that they all share the pool
+    // and per-request settings.
+    async fn execute(&self, request: RequestBuilder, exchange: Exchange) -> Result<Response, TimebaseError> {
+        let request = match exchange {
+            Exchange::Buffered | Exchange::Write => request.timeout(self.timeout),
+            // A total timeout would cut off long streams, so only the wait is bounded
+            Exchange::Streamed => request,
+        };
+        let mut attempt = 1;
+
+        loop {
+            // Requests with streaming bodies cannot be cloned and are only sent once
+            let Some(this_attempt) = request.try_clone() else {
+                return self.send_attempt(request, exchange).await;
+            };
+
+            let outcome = self.send_attempt(this_attempt, exchange).await;
+            if attempt >= self.retry.attempts(exchange == Exchange::Write) {
+                return outcome;
+            }
+
+            let error = match outcome {
+                Ok(resp) if self.retry.retries_status(resp.status()) => match check_status(resp).await {
+                    Ok(resp) => return Ok(resp),
+                    Err(e) => e,
+                },
+                Ok(resp) => return Ok(resp),
+                Err(e) if self.retry.retries_error(&e) => e,
+                Err(e) => return Err(e),
+            };
+
+            let delay = self.retry.delay(attempt);
+            warn!(attempt, delay_ms = delay.as_millis() as u64, error = %error, "retrying request");
+            self.retry.report(&RetryEvent { attempt, delay, error: &error });
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    async fn send_attempt(&self, request: RequestBuilder, exchange: Exchange) -> Result<Response, TimebaseError> {
+        match exchange {
+            Exchange::Buffered | Exchange::Write => self.send_authorized(request).await,
+            Exchange::Streamed => tokio::time::timeout(self.timeout, self.send_authorized(request))
+                .await
+                .unwrap_or(Err(TimebaseError::Stalled(self.timeout))),
+        }
+    }
+
+    async fn send_authorized(&self, request: RequestBuilder) -> Result<Response, TimebaseError> {
+        let Some(auth) = &self.auth else {
+            return Ok(request.send().await?);
+        };
+
+        let retry = request.try_clone();
+        let resp = auth.apply(request).await?.send().await?;
+
+        match retry {
+            Some(retry) if resp.status() == StatusCode::UNAUTHORIZED && auth.refresh() => {
+                Ok(auth.apply(retry).await?.send().await?)
+            }
+            _ => Ok(resp),
+        }
+    }
+
+    // Appends path segments to the base URL, percent-encoding each one so dataset and tag
+    // names may contain spaces or slashes.
+    fn endpoint(&self, segments: &[&str]) -> Result<Url, TimebaseError> {
+        let mut url = self.base_url.clone();
+
+        match url.path_segments_mut() {
+            Ok(mut path) => {
+                path.pop_if_empty().extend(segments);
+            }
+            Err(_) => return Err(TimebaseError::InvalidUrl(format!("{} cannot be a base URL", self.base_url)))
+        }
+
+        Ok(url)
+    }
+
     pub fn get_data<'a>(&'a self, dataset: &'a str) -> GetDataRequestBuilder<'a> {
         GetDataRequestBuilder {
             client: self,
@@ -145,10 +375,17 @@ impl TimebaseClient {
             relative_start: None,
             relative_end: None,
             tag_names: vec![],
+            chunking: None,
+            max_concurrency: 4,
         }
     }
 }
 
+impl Default for TimebaseClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 pub struct GetDataRequestBuilder<'a> {
     client: &'a TimebaseClient,
@@ -158,6 +395,31 @@ pub struct GetDataRequestBuilder<'a> {
     relative_start: Option<&'a str>,
     relative_end: Option<&'a str>,
     tag_names: Vec<&'a str>,
+    chunking: Option<Chunking>,
+    max_concurrency: usize,
+}
+
+/// How a long time range is split into several smaller requests.
+#[derive(Debug, Clone, Copy)]
+pub enum Chunking {
+    /// Windows of a fixed length.
+    Duration(TimeDelta),
+
+    /// Windows sized to hold about `points` points per tag, given the interval at which the
+    /// tags are expected to be sampled.
+    Points { points: u32, sample_interval: TimeDelta },
+}
+
+impl Chunking {
+    fn window(&self) -> Result<TimeDelta, TimebaseError> {
+        match *self {
+            Chunking::Duration(window) => Ok(window),
+            Chunking::Points { points, sample_interval } => i32::try_from(points)
+                .ok()
+                .and_then(|points| sample_interval.checked_mul(points))
+                .ok_or_else(|| TimebaseError::InvalidTime(format!("{} points of {} overflow the chunk window", points, sample_interval))),
+        }
+    }
 }
 
 impl<'a> GetDataRequestBuilder<'a> {
@@ -171,11 +433,36 @@ impl<'a> GetDataRequestBuilder<'a> {
         self
     }
 
-    pub fn start_iso(mut self, start: &'a str) -> Result<Self, Box<dyn std::error::Error>> {
-        self.start = Some(DateTime::parse_from_rfc3339(start)?);
+    pub fn start_iso(mut self, start: &'a str) -> Result<Self, TimebaseError> {
+        match DateTime::parse_from_rfc3339(start) {
+            Ok(start) => self.start = Some(start),
+            Err(e) => return Err(TimebaseError::InvalidTime(format!("{}: {}", start, e)))
+        }
+        Ok(self)
+    }
+
+    pub fn end_iso(mut self, end: &'a str) -> Result<Self, TimebaseError> {
+        match DateTime::parse_from_rfc3339(end) {
+            Ok(end) => self.end = Some(end),
+            Err(e) => return Err(TimebaseError::InvalidTime(format!("{}: {}", end, e)))
+        }
         Ok(self)
     }
 
+    /// Sets the start of the range as a Timebase relative time expression such as `*-8h`.
+    /// Surrounding whitespace is dropped and the expression is validated when the request is built.
+    pub fn relative_start(mut self, start: &'a str) -> Self {
+        self.relative_start = Some(start.trim());
+        self
+    }
+
+    /// Sets the end of the range as a Timebase relative time expression such as `*`.
+    /// Surrounding whitespace is dropped and the expression is validated when the request is built.
+    pub fn relative_end(mut self, end: &'a str) -> Self {
+        self.relative_end = Some(end.trim());
+        self
+    }
+
     pub fn tag_name(mut self, tag_name: &'a str) -> Self {
         self.tag_names.push(tag_name);
         self
@@ -186,8 +473,98 @@ impl<'a> GetDataRequestBuilder<'a> {
         self
     }
 
-    pub fn build(self) -> Result<GetDataRequest, Box<dyn std::error::Error>> {
-        let mut url = self.client.base_url.clone().join(&format!("api/datasets/{}/data", self.dataset_name))?;
+    /// Splits the range into windows of `window` length that are fetched separately and
+    /// merged into one response. Requires an absolute start and end.
+    pub fn chunk_duration(mut self, window: TimeDelta) -> Self {
+        self.chunking = Some(Chunking::Duration(window));
+        self
+    }
+
+    /// Splits the range into windows holding about `points` points per tag when the tags are
+    /// sampled every `sample_interval`. Requires an absolute start and end.
+    pub fn chunk_points(mut self, points: u32, sample_interval: TimeDelta) -> Self {
+        self.chunking = Some(Chunking::Points { points, sample_interval });
+        self
+    }
+
+    /// Sets how many chunk requests may be in flight at once. Defaults to 4.
+    pub fn max_concurrency(mut self, max_concurrency: usize) -> Self {
+        self.max_concurrency = max_concurrency.max(1);
+        self
+    }
+
+    pub fn build(self) -> Result<GetDataRequest<'a>, TimebaseError> {
+        if self.start.is_some() && self.relative_start.is_some() {
+            return Err(TimebaseError::InvalidTime("Both an absolute and a relative start time were given".into()));
+        }
+
+        if self.end.is_some() && self.relative_end.is_some() {
+            return Err(TimebaseError::InvalidTime("Both an absolute and a relative end time were given".into()));
+        }
+
+        for expression in [self.relative_start, self.relative_end].into_iter().flatten() {
+            if !is_valid_relative_time(expression) {
+                return Err(TimebaseError::InvalidTime(format!("Invalid relative time \"{}\"", expression)));
+            }
+        }
+
+        let start = match self.start {
+            Some(start) => Some(start.to_rfc3339()),
+            None => self.relative_start.map(str::to_string),
+        };
+
+        let end = match self.end {
+            Some(end) => Some(end.to_rfc3339()),
+            None => self.relative_end.map(str::to_string),
+        };
+
+        let urls = match self.chunking {
+            None => vec![self.data_url(start.as_deref(), end.as_deref())?],
+            Some(chunking) => {
+                let (Some(start), Some(end)) = (self.start, self.end) else {
+                    return Err(TimebaseError::InvalidTime("Chunked requests need an absolute start and end".into()));
+                };
+
+                let window = chunking.window()?;
+                if window <= TimeDelta::zero() {
+                    return Err(TimebaseError::InvalidTime(format!("Invalid chunk window {}", window)));
+                }
+
+                if start >= end {
+                    return Err(TimebaseError::InvalidTime(format!("Start {} is not before end {}", start, end)));
+                }
+
+                let mut urls = vec![];
+                let mut window_start = start;
+
+                while window_start < end {
+                    // A window reaching past the representable dates simply ends the range
+                    let window_end = window_start.checked_add_signed(window).map_or(end, |t| t.min(end));
+                    urls.push(self.data_url(Some(&window_start.to_rfc3339()), Some(&window_end.to_rfc3339()))?);
+                    window_start = window_end;
+                }
+
+                urls
+            }
+        };
+
+        Ok(GetDataRequest {
+            client: self.client,
+            urls,
+            max_concurrency: self.max_concurrency,
+            chunking: self.chunking,
+            range: self.start.zip(self.end).map(|(start, end)| (start.to_utc(), end.to_utc())),
+            start,
+            end,
+            dataset_name: self.dataset_name.to_string(),
+            tag_names: self.tag_names.iter().map(|t| t.to_string()).collect(),
+        })
+    }
+}
+
+impl GetDataRequestBuilder<'_> {
+    fn data_url(&self, start: Option<&str>, end: Option<&str>) -> Result<Url, TimebaseError> {
+        let mut url = self.client.endpoint(&["api", "datasets", self.dataset_name, "data"])?;
 
         {
             let mut query_pairs = url.query_pairs_mut();
@@ -196,41 +573,312 @@ impl<'a> GetDataRequestBuilder<'a> {
                 query_pairs.append_pair("tagname", tag_name);
             });
 
-            if let Some(start) = self.start {
-                query_pairs.append_pair("start", start.to_rfc3339().as_str());
+            if let Some(start) = start {
+                query_pairs.append_pair("start", start);
             }
 
-            if let Some(end) = self.end {
-                query_pairs.append_pair("end", end.to_rfc3339().as_str());
+            if let Some(end) = end {
+                query_pairs.append_pair("end", end);
             }
         }
 
-        Ok(GetDataRequest { url, timeout: self.client.timeout })
+        Ok(url)
     }
 }
 
-pub struct GetDataRequest {
-    url: Url,
-    timeout: Duration,
-}
+// Relative times are `*` (now) followed by any number of signed offsets, e.g. `*-8h` or
+// `*-1d+6h`. Supported units are ms, s, m, h, d, w, mo and y.
+fn is_valid_relative_time(expression: &str) -> bool {
+    let Some(mut rest) = expression.strip_prefix('*') else {
+        return false;
+    };
+
+    while !rest.is_empty() {
+        rest = match rest.strip_prefix(['+', '-']) {
+            Some(rest) => rest,
+            None => return false,
+        };
+
+        let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        if digits == 0 {
+            return false;
+        }
+        rest = &rest[digits..];
 
-impl GetDataRequest {
-    pub async fn send(&self) -> Result<GetDataResponse, Box<dyn std::error::Error>> {
-        let url = self.url.clone();
-        let client = Client::builder()
-            .timeout(self.timeout)
-            .build()?;
+        let units = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_alphabetic()).len();
+        match &rest[..units] {
+            "ms" | "s" | "m" | "h" | "d" | "w" | "mo" | "y" => rest = &rest[units..],
+            _ => return false,
+        }
+    }
 
-        println!("GET {}", url);
+    true
+}
 
-        let resp = client.get(url).send().await?;
+pub struct GetDataRequest<'a> {
+    client: &'a TimebaseClient,
+    urls: Vec<Url>,
+    max_concurrency: usize,
+    chunking: Option<Chunking>,
+    range: Option<(DateTime<Utc>, DateTime<Utc>)>,
+    start: Option<String>,
+    end: Option<String>,
+    dataset_name: String,
+    tag_names: Vec<String>,
+}
 
-        if !resp.status().is_success() {
-            return Err(format!("HTTP request failed with status code {}", resp.status()).into());
+impl GetDataRequest<'_> {
+    /// Sends the request. A chunked request fetches its windows concurrently and merges
+    /// them into a single response. With a cache set on the client, an absolute range is
+    /// served from the cache as far as possible.
+    pub async fn send(&self) -> Result<GetDataResponse, TimebaseError> {
+        match (&self.client.cache, self.range) {
+            (Some(cache), Some((start, end))) => {
+                self.send_cached(cache, start, end).instrument(self.span("cached")).await
+            }
+            _ => self.fetch_all().instrument(self.span("buffered")).await,
         }
+    }
+
+    async fn fetch_all(&self) -> Result<GetDataResponse, TimebaseError> {
+        let started = Instant::now();
+
+        let data = match self.urls.as_slice() {
+            [url] => self.fetch(url).await?,
+            urls => {
+                let requests = urls.iter().map(|url| self.fetch(url)).collect();
+                let chunks = try_buffered(requests, self.max_concurrency).await?;
+
+                // build() never produces a request without windows
+                merge_chunks(chunks).expect("chunked request has at least one window")
+            }
+        };
 
-        let data: GetDataResponse = resp.json().await?;
+        info!(
+            points = data.tags.iter().map(|item| item.data.len()).sum::<usize>(),
+            latency_ms = started.elapsed().as_millis() as u64,
+            "data received"
+        );
 
         Ok(data)
     }
-}
\ No newline at end of file
+
+    fn span(&self, mode: &'static str) -> Span {
+        info_span!(
+            "get_data",
+            mode,
+            dataset = %self.dataset_name,
+            tag_count = self.tag_names.len(),
+            start = self.start.as_deref(),
+            end = self.end.as_deref(),
+            windows = self.urls.len(),
+        )
+    }
+
+    async fn fetch(&self, url: &Url) -> Result<GetDataResponse, TimebaseError> {
+        let data: GetDataResponse = read_json(self.open(url, Exchange::Buffered).await?).await?;
+
+        self.check_tags(data.tags.iter().map(|item| item.tag.name.as_str()))?;
+
+        Ok(data)
+    }
+
+    async fn open(&self, url: &Url, exchange: Exchange) -> Result<Response, TimebaseError> {
+        debug!(%url, "GET");
+
+        let resp = self.client.execute(self.client.http.get(url.clone()), exchange).await?;
+
+        if resp.status() == StatusCode::NOT_FOUND {
+            return Err(TimebaseError::UnknownDataset(self.dataset_name.clone()));
+        }
+
+        check_status(resp).await
+    }
+
+    // Timebase leaves tags it does not know out of the response rather than failing
+    fn check_tags<'n>(&self, received: impl Iterator<Item = &'n str> + Clone) -> Result<(), TimebaseError> {
+        match self.tag_names.iter().find(|name| !received.clone().any(|r| r.eq_ignore_ascii_case(name))) {
+            Some(missing) => Err(TimebaseError::UnknownTag {
+                dataset: self.dataset_name.clone(),
+                tag: missing.clone(),
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+// Joins chunk responses in time order. Each chunk repeats the value in effect at its start,
+// so points at or before the last point already kept for a tag are dropped.
+fn merge_chunks(chunks: Vec<GetDataResponse>) -> Option<GetDataResponse> {
+    let mut chunks = chunks.into_iter();
+    let mut merged = chunks.next()?;
+
+    for chunk in chunks {
+        merged.end = chunk.end;
+
+        for item in chunk.tags {
+            match merged.tags.iter_mut().find(|t| t.tag.name == item.tag.name) {
+                Some(existing) => {
+                    let last = existing.data.last().map(|d| d.timestamp);
+                    existing.data.extend(item.data.into_iter().filter(|d| Some(d.timestamp) > last));
+                }
+                None => merged.tags.push(item),
+            }
+        }
+    }
+
+    Some(merged)
+}
+
+// Runs the requests at most `limit` at a time and collects their results in order. The
+// futures are built by the caller: creating them in a closure inside the stream keeps the
+// future of send() from being Send.
+async fn try_buffered<T, F>(requests: Vec<F>, limit: usize) -> Result<Vec<T>, TimebaseError>
+where
+    F: Future<Output = Result<T, TimebaseError>>,
+{
+    futures::stream::iter(requests).buffered(limit).try_collect().await
+}
+
+// Turns a non-success response into TimebaseError::Status, keeping the body for diagnostics.
+async fn check_status(resp: Response) -> Result<Response, TimebaseError> {
+    let status = resp.status();
+
+    if status.is_success() {
+        return Ok(resp);
+    }
+
+    let url = resp.url().clone();
+    let body = resp.text().await.unwrap_or_default();
+
+    Err(TimebaseError::Status { url, status, body })
+}
+
+// Checks the status of a response and decodes its body, keeping the JSON path of any
+// decode failure so malformed historian responses can be tracked down.
+async fn read_json<T: DeserializeOwned>(resp: Response) -> Result<T, TimebaseError> {
+    let resp = check_status(resp).await?;
+    let url = resp.url().clone();
+    let bytes = resp.bytes().await?;
+
+    debug!(%url, response_bytes = bytes.len(), "response received");
+
+    let deserializer = &mut serde_json::Deserializer::from_slice(&bytes);
+
+    serde_path_to_error::deserialize(deserializer).map_err(|e| TimebaseError::Decode {
+        path: e.path().to_string(),
+        source: e.into_inner(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Fails to compile if the future of send() stops being Send, e.g. for tokio::spawn
+    #[allow(dead_code)]
+    fn assert_send<'r>(request: &'r GetDataRequest<'_>) -> impl Send + 'r {
+        request.send()
+    }
+
+    #[test]
+    fn relative_times() {
+        for expression in ["*", "*-8h", "*-1d+6h", "*-500ms", "*+1mo-2w", "*-1y"] {
+            assert!(is_valid_relative_time(expression), "{}", expression);
+        }
+
+        for expression in ["", "now", "*-", "*-h", "*8h", "*-8", "*-8x", "*-1.5h", "*-8hours", "**", " *"] {
+            assert!(!is_valid_relative_time(expression), "{}", expression);
+        }
+    }
+
+    #[test]
+    fn build_rejects_invalid_times() {
+        let client = TimebaseClient::default();
+
+        let invalid_relative = client.get_data("ds").tag_name("A").relative_start("yesterday").build();
+        assert!(matches!(invalid_relative, Err(TimebaseError::InvalidTime(_))));
+
+        let both = client.get_data("ds").tag_name("A").start_iso("2025-01-01T00:00:00Z").unwrap().relative_start("*-1d").build();
+        assert!(matches!(both, Err(TimebaseError::InvalidTime(_))));
+    }
+
+    #[test]
+    fn relative_times_are_sent_trimmed() {
+        let client = TimebaseClient::default();
+        let request = client.get_data("ds").tag_name("A").relative_start(" *-1d+6h").relative_end("*\n").build().unwrap();
+
+        let query: Vec<(String, String)> = request.urls[0].query_pairs().into_owned().collect();
+        assert!(query.contains(&("start".to_string(), "*-1d+6h".to_string())));
+        assert!(query.contains(&("end".to_string(), "*".to_string())));
+    }
+
+    #[test]
+    fn chunk_points_overflow_is_an_error() {
+        let client = TimebaseClient::default();
+        let request = client.get_data("ds").tag_name("A")
+            .start_iso("2025-01-01T00:00:00Z").unwrap()
+            .end_iso("2025-01-02T00:00:00Z").unwrap();
+
+        let too_many = request.chunk_points(u32::MAX, TimeDelta::seconds(1)).build();
+        assert!(matches!(too_many, Err(TimebaseError::InvalidTime(_))));
+
+        let request = client.get_data("ds").tag_name("A")
+            .start_iso("2025-01-01T00:00:00Z").unwrap()
+            .end_iso("2025-01-02T00:00:00Z").unwrap();
+
+        // A window past the representable dates is not an overflow: the range is one chunk
+        let huge = request.chunk_points(1_000_000, TimeDelta::days(365_000)).build().unwrap();
+        assert_eq!(huge.urls.len(), 1);
+    }
+
+    fn chunk(start: i64, end: i64, tags: &[(&str, &[i64])]) -> GetDataResponse {
+        let time = |seconds: i64| Utc.timestamp_opt(1_735_689_600 + seconds, 0).unwrap();
+
+        GetDataResponse {
+            start: time(start),
+            end: time(end),
+            tags: tags
+                .iter()
+                .map(|(name, seconds)| TagItem {
+                    tag: Tag {
+                        name: name.to_string(),
+                        description: None,
+                        format: None,
+                        uom: None,
+                        fields: None,
+                        data_type: None,
+                    },
+                    data: seconds
+                        .iter()
+                        .map(|s| TagData { timestamp: time(*s), value: Some(TagValue::Integer(*s as i32)), quality: 192 })
+                        .collect(),
+                })
+                .collect(),
+        }
+    }
+
+    fn seconds(response: &GetDataResponse, name: &str) -> Vec<i64> {
+        let item = response.tags.iter().find(|item| item.tag.name == name).unwrap();
+        item.data.iter().map(|d| d.timestamp.timestamp() - 1_735_689_600).collect()
+    }
+
+    #[test]
+    fn merge_chunks_drops_repeated_boundary_points() {
+        // Each chunk repeats the value in effect at its start
+        let merged = merge_chunks(vec![
+            chunk(0, 10, &[("A", &[0, 5, 10]), ("B", &[2])]),
+            chunk(10, 20, &[("A", &[10, 15]), ("B", &[2, 12])]),
+            chunk(20, 30, &[("A", &[15, 25]), ("C", &[21])]),
+        ])
+        .unwrap();
+
+        assert_eq!(merged.start, chunk(0, 0, &[]).start);
+        assert_eq!(merged.end, chunk(30, 30, &[]).end);
+        assert_eq!(seconds(&merged, "A"), vec![0, 5, 10, 15, 25]);
+        assert_eq!(seconds(&merged, "B"), vec![2, 12]);
+        assert_eq!(seconds(&merged, "C"), vec![21]);
+
+        assert!(merge_chunks(vec![]).is_none());
+    }
+}
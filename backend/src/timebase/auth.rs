@@ -0,0 +1,255 @@
+use super::TimebaseError;
+use chrono::{DateTime, TimeDelta, Utc};
+use futures::future::BoxFuture;
+use reqwest::header::{HeaderName, HeaderValue};
+use reqwest::RequestBuilder;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+/// Credentials attached to every request a [`super::TimebaseClient`] sends.
+#[derive(Clone)]
+pub enum Auth {
+    /// `Authorization: Bearer <token>`
+    Bearer(String),
+
+    /// `Authorization: Basic ...`
+    Basic {
+        username: String,
+        password: Option<String>,
+    },
+
+    /// A static key sent in a custom header, e.g. `X-Api-Key`.
+    ApiKey { header: HeaderName, value: HeaderValue },
+
+    /// A bearer token obtained from a provider before each request.
+    Provider(Arc<dyn TokenProvider>),
+}
+
+impl Auth {
+    pub fn bearer(token: &str) -> Self {
+        Auth::Bearer(token.to_string())
+    }
+
+    pub fn basic(username: &str, password: Option<&str>) -> Self {
+        Auth::Basic {
+            username: username.to_string(),
+            password: password.map(str::to_string),
+        }
+    }
+
+    pub fn api_key(header: &str, key: &str) -> Result<Self, TimebaseError> {
+        let header = match HeaderName::from_bytes(header.as_bytes()) {
+            Ok(header) => header,
+            Err(_) => return Err(TimebaseError::Configuration(format!("Invalid header name {}", header)))
+        };
+        let mut value = match HeaderValue::from_str(key) {
+            Ok(value) => value,
+            Err(_) => return Err(TimebaseError::Configuration(format!("Invalid value for header {}", header)))
+        };
+        value.set_sensitive(true);
+
+        Ok(Auth::ApiKey { header, value })
+    }
+
+    pub fn provider<P: TokenProvider + 'static>(provider: P) -> Self {
+        Auth::Provider(Arc::new(provider))
+    }
+
+    pub(super) async fn apply(&self, request: RequestBuilder) -> Result<RequestBuilder, TimebaseError> {
+        Ok(match self {
+            Auth::Bearer(token) => request.bearer_auth(token),
+            Auth::Basic { username, password } => request.basic_auth(username, password.as_ref()),
+            Auth::ApiKey { header, value } => request.header(header, value),
+            Auth::Provider(provider) => request.bearer_auth(provider.token().await?),
+        })
+    }
+
+    // A rejected provider token may just have expired, so it is worth one fresh attempt
+    pub(super) fn refresh(&self) -> bool {
+        match self {
+            Auth::Provider(provider) => {
+                provider.invalidate();
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Debug for Auth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Auth::Bearer(_) => f.write_str("Bearer(***)"),
+            Auth::Basic { username, .. } => write!(f, "Basic({}, ***)", username),
+            Auth::ApiKey { header, .. } => write!(f, "ApiKey({}: ***)", header),
+            Auth::Provider(_) => f.write_str("Provider"),
+        }
+    }
+}
+
+/// Supplies bearer tokens for [`Auth::Provider`], e.g. from an OAuth token endpoint.
+pub trait TokenProvider: Send + Sync {
+    /// Returns a token that is valid now, fetching or refreshing it if needed.
+    fn token(&self) -> BoxFuture<'_, Result<String, TimebaseError>>;
+
+    /// Called when the server rejected the last token with 401 Unauthorized, so the next call
+    /// to `token` must not return it again.
+    fn invalidate(&self) {}
+}
+
+/// A token together with the time it stops being valid.
+pub struct ExpiringToken {
+    pub token: String,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// [`TokenProvider`] that caches the token returned by `fetch` and calls it again shortly
+/// before the token expires or after the server rejects it.
+pub struct RefreshingTokenProvider<F> {
+    fetch: F,
+    refresh_margin: TimeDelta,
+    cached: Mutex<Option<ExpiringToken>>,
+}
+
+impl<F> RefreshingTokenProvider<F>
+where
+    F: Fn() -> BoxFuture<'static, Result<ExpiringToken, TimebaseError>> + Send + Sync,
+{
+    pub fn new(fetch: F) -> Self {
+        Self {
+            fetch,
+            refresh_margin: TimeDelta::seconds(30),
+            cached: Mutex::new(None),
+        }
+    }
+
+    /// Sets how long before expiry a token is refreshed. Defaults to 30 seconds.
+    pub fn refresh_margin(mut self, margin: TimeDelta) -> Self {
+        self.refresh_margin = margin;
+        self
+    }
+
+    fn cached_token(&self) -> Option<String> {
+        let cached = self.cached.lock().unwrap();
+
+        cached
+            .as_ref()
+            .filter(|t| t.expires_at.is_none_or(|expires_at| Utc::now() + self.refresh_margin < expires_at))
+            .map(|t| t.token.clone())
+    }
+}
+
+impl<F> TokenProvider for RefreshingTokenProvider<F>
+where
+    F: Fn() -> BoxFuture<'static, Result<ExpiringToken, TimebaseError>> + Send + Sync,
+{
+    fn token(&self) -> BoxFuture<'_, Result<String, TimebaseError>> {
+        Box::pin(async move {
+            if let Some(token) = self.cached_token() {
+                return Ok(token);
+            }
+
+            let fresh = (self.fetch)().await?;
+            let token = fresh.token.clone();
+            *self.cached.lock().unwrap() = Some(fresh);
+
+            Ok(token)
+        })
+    }
+
+    fn invalidate(&self) {
+        *self.cached.lock().unwrap() = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    type Fetch = Box<dyn Fn() -> BoxFuture<'static, Result<ExpiringToken, TimebaseError>> + Send + Sync>;
+
+    // Fetches "token-1", "token-2", ... that expire `lifetime` after they are fetched
+    fn counting(lifetime: Option<TimeDelta>) -> (Arc<AtomicUsize>, Fetch) {
+        let count = Arc::new(AtomicUsize::new(0));
+        let fetches = count.clone();
+
+        let fetch: Fetch = Box::new(move || {
+            let n = fetches.fetch_add(1, Ordering::SeqCst) + 1;
+            Box::pin(async move {
+                Ok(ExpiringToken {
+                    token: format!("token-{}", n),
+                    expires_at: lifetime.map(|lifetime| Utc::now() + lifetime),
+                })
+            })
+        });
+
+        (count, fetch)
+    }
+
+    #[tokio::test]
+    async fn token_is_cached_until_it_nears_expiry() {
+        let (count, fetch) = counting(Some(TimeDelta::hours(1)));
+        let provider = RefreshingTokenProvider::new(fetch);
+
+        assert_eq!(provider.token().await.unwrap(), "token-1");
+        assert_eq!(provider.token().await.unwrap(), "token-1");
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+
+        let (count, fetch) = counting(None);
+        let provider = RefreshingTokenProvider::new(fetch);
+
+        provider.token().await.unwrap();
+        assert_eq!(provider.token().await.unwrap(), "token-1");
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn token_within_the_margin_is_refreshed() {
+        // Expires in 20 s, inside the default 30 s margin
+        let (count, fetch) = counting(Some(TimeDelta::seconds(20)));
+        let provider = RefreshingTokenProvider::new(fetch);
+
+        assert_eq!(provider.token().await.unwrap(), "token-1");
+        assert_eq!(provider.token().await.unwrap(), "token-2");
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+
+        let (count, fetch) = counting(Some(TimeDelta::seconds(20)));
+        let provider = RefreshingTokenProvider::new(fetch).refresh_margin(TimeDelta::seconds(10));
+
+        provider.token().await.unwrap();
+        assert_eq!(provider.token().await.unwrap(), "token-1");
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn invalidated_token_is_fetched_again() {
+        let (count, fetch) = counting(None);
+        let provider = RefreshingTokenProvider::new(fetch);
+
+        provider.token().await.unwrap();
+        provider.invalidate();
+
+        assert_eq!(provider.token().await.unwrap(), "token-2");
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn failed_fetch_is_not_cached() {
+        let count = Arc::new(AtomicUsize::new(0));
+        let fetches = count.clone();
+        let provider = RefreshingTokenProvider::new(move || -> BoxFuture<'static, Result<ExpiringToken, TimebaseError>> {
+            let n = fetches.fetch_add(1, Ordering::SeqCst) + 1;
+            Box::pin(async move {
+                match n {
+                    1 => Err(TimebaseError::Authentication("token endpoint unavailable".into())),
+                    _ => Ok(ExpiringToken { token: format!("token-{}", n), expires_at: None }),
+                }
+            })
+        });
+
+        assert!(matches!(provider.token().await, Err(TimebaseError::Authentication(_))));
+        assert_eq!(provider.token().await.unwrap(), "token-2");
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,769 @@
+use super::{try_buffered, GetDataRequest, GetDataResponse, Tag, TagData, TagItem, TimebaseError};
+use chrono::{DateTime, DurationRound, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+use tracing::{debug, info, warn};
+
+type Range = (DateTime<Utc>, DateTime<Utc>);
+
+/// Identifies the cached data of one tag. Timebase names are not case sensitive, so keys are
+/// kept in lower case.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    pub dataset: String,
+    pub tag: String,
+}
+
+impl CacheKey {
+    pub fn new(dataset: &str, tag: &str) -> Self {
+        Self {
+            dataset: dataset.to_lowercase(),
+            tag: tag.to_lowercase(),
+        }
+    }
+}
+
+/// The cached data of one tag: its metadata and the time ranges held, each with every point
+/// Timebase returned for it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheEntry {
+    tag: Tag,
+    segments: Vec<Segment>,
+}
+
+/// A time range of one tag held in the cache.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Segment {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    /// Includes the value in effect at `start`, which may be older than `start`.
+    pub data: Vec<TagData>,
+}
+
+impl Segment {
+    // The part of the segment within `start..=end`, keeping the value in effect at its start
+    fn slice(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> Segment {
+        let (start, end) = (start.max(self.start), end.min(self.end));
+        let first = self.data.partition_point(|d| d.timestamp <= start).saturating_sub(1);
+        let last = self.data.partition_point(|d| d.timestamp <= end);
+
+        Segment { start, end, data: self.data[first..last.max(first)].to_vec() }
+    }
+}
+
+impl CacheEntry {
+    pub fn new(tag: Tag) -> Self {
+        Self { tag, segments: vec![] }
+    }
+
+    /// Number of points held, used to enforce size limits.
+    pub fn points(&self) -> usize {
+        self.segments.iter().map(|s| s.data.len()).sum()
+    }
+
+    /// The entry cut down to the segments overlapping `start..end`, each limited to the range.
+    pub fn slice(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> CacheEntry {
+        CacheEntry {
+            tag: self.tag.clone(),
+            segments: self.segments.iter()
+                .filter(|s| s.end > start && s.start < end)
+                .map(|s| s.slice(start, end))
+                .collect(),
+        }
+    }
+
+    // Parts of `start..end` not covered by a segment
+    fn missing(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> Vec<Range> {
+        let mut missing = vec![];
+        let mut covered_to = start;
+
+        for segment in self.segments.iter().filter(|s| s.end > start && s.start < end) {
+            if segment.start > covered_to {
+                missing.push((covered_to, segment.start));
+            }
+            covered_to = covered_to.max(segment.end);
+        }
+
+        if covered_to < end {
+            missing.push((covered_to, end));
+        }
+
+        missing
+    }
+
+    /// Adds a segment, merging it with any segments it overlaps or touches.
+    pub fn insert(&mut self, segment: Segment) {
+        self.segments.push(segment);
+        self.segments.sort_by_key(|s| s.start);
+
+        let mut merged: Vec<Segment> = vec![];
+        for segment in self.segments.drain(..) {
+            match merged.last_mut() {
+                Some(last) if segment.start <= last.end => {
+                    last.end = last.end.max(segment.end);
+                    last.data = merge_points(std::mem::take(&mut last.data), segment.data);
+                }
+                _ => merged.push(segment),
+            }
+        }
+
+        self.segments = merged;
+    }
+
+    // The points of the segment covering `start..=end`, starting with the value in effect at
+    // `start`. Only called once the whole range is covered.
+    fn points_in(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> &[TagData] {
+        let Some(segment) = self.segments.iter().find(|s| s.start <= start && s.end >= end) else {
+            return &[];
+        };
+
+        let first = segment.data.partition_point(|d| d.timestamp <= start).saturating_sub(1);
+        let last = segment.data.partition_point(|d| d.timestamp <= end);
+
+        &segment.data[first..last.max(first)]
+    }
+}
+
+// Merges two time-ordered runs of points, keeping the newer copy of a repeated timestamp
+fn merge_points(old: Vec<TagData>, new: Vec<TagData>) -> Vec<TagData> {
+    let mut points = old;
+    points.extend(new);
+    // Stable, so a new point stays after an old one with the same timestamp
+    points.sort_by_key(|d| d.timestamp);
+
+    let mut merged: Vec<TagData> = Vec::with_capacity(points.len());
+    for point in points {
+        match merged.last_mut() {
+            Some(last) if last.timestamp == point.timestamp => *last = point,
+            _ => merged.push(point),
+        }
+    }
+
+    merged
+}
+
+/// Where cached data is kept. Stores enforce their own size limit when segments are added.
+pub trait CacheStore: Send + Sync {
+    /// The data held for a tag over `start..end`, see [`CacheEntry::slice`].
+    fn get(&self, key: &CacheKey, start: DateTime<Utc>, end: DateTime<Utc>) -> Option<CacheEntry>;
+
+    /// Adds a segment to the data held for a tag, replacing its metadata with `tag`. Updates
+    /// of the same tag from concurrent requests must all be kept.
+    fn insert(&self, key: &CacheKey, tag: &Tag, segment: Segment);
+
+    fn clear(&self);
+}
+
+/// Keeps entries in memory, evicting the least recently used tags once more than
+/// `max_points` points are held.
+pub struct MemoryStore {
+    max_points: usize,
+    entries: Mutex<MemoryEntries>,
+}
+
+#[derive(Default)]
+struct MemoryEntries {
+    tick: u64,
+    entries: HashMap<CacheKey, (CacheEntry, u64)>,
+}
+
+impl MemoryStore {
+    pub fn new(max_points: usize) -> Self {
+        Self {
+            max_points,
+            entries: Default::default(),
+        }
+    }
+}
+
+impl CacheStore for MemoryStore {
+    fn get(&self, key: &CacheKey, start: DateTime<Utc>, end: DateTime<Utc>) -> Option<CacheEntry> {
+        let mut memory = self.entries.lock().unwrap();
+        memory.tick += 1;
+        let tick = memory.tick;
+
+        let (entry, used) = memory.entries.get_mut(key)?;
+        *used = tick;
+
+        Some(entry.slice(start, end))
+    }
+
+    fn insert(&self, key: &CacheKey, tag: &Tag, segment: Segment) {
+        let mut memory = self.entries.lock().unwrap();
+        memory.tick += 1;
+        let tick = memory.tick;
+
+        let (entry, used) = memory.entries.entry(key.clone()).or_insert_with(|| (CacheEntry::new(tag.clone()), tick));
+        entry.tag = tag.clone();
+        entry.insert(segment);
+        *used = tick;
+
+        let mut total: usize = memory.entries.values().map(|(e, _)| e.points()).sum();
+        while total > self.max_points {
+            let Some(oldest) = memory.entries.iter().min_by_key(|(_, (_, used))| *used).map(|(k, _)| k.clone()) else {
+                break;
+            };
+
+            if let Some((evicted, _)) = memory.entries.remove(&oldest) {
+                debug!(dataset = %oldest.dataset, tag = %oldest.tag, points = evicted.points(), "evicted from cache");
+                total -= evicted.points();
+            }
+        }
+    }
+
+    fn clear(&self) {
+        self.entries.lock().unwrap().entries.clear();
+    }
+}
+
+/// Keeps a directory per tag in `dir`, so the cache survives restarts. Segments are split at
+/// UTC day boundaries and each piece is a JSON file, so a request only reads the days it
+/// covers. Once the files take more than `max_bytes`, the least recently used are deleted.
+pub struct DiskStore {
+    dir: PathBuf,
+    max_bytes: u64,
+    // Adding a segment rewrites the pieces it merges with
+    writing: Mutex<()>,
+}
+
+// A piece file of a tag's directory, named after the range it holds
+struct Piece {
+    path: PathBuf,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+}
+
+impl DiskStore {
+    /// Opens a store in `dir`, creating the directory if needed.
+    pub fn new(dir: impl Into<PathBuf>, max_bytes: u64) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+
+        Ok(Self { dir, max_bytes, writing: Mutex::new(()) })
+    }
+
+    // Directory names spell out the dataset and tag, escaping anything unsafe in a path
+    fn tag_dir(&self, key: &CacheKey) -> PathBuf {
+        let escape = |name: &str| -> String {
+            name.bytes().map(|b| match b {
+                b'a'..=b'z' | b'0'..=b'9' | b'.' | b'-' | b'_' => (b as char).to_string(),
+                _ => format!("%{:02X}", b),
+            }).collect()
+        };
+
+        self.dir.join(format!("{}~{}", escape(&key.dataset), escape(&key.tag)))
+    }
+
+    // Piece files are named `<start>_<end>.json` in milliseconds, rounded outwards
+    fn piece_path(dir: &Path, start: DateTime<Utc>, end: DateTime<Utc>) -> PathBuf {
+        let end_ms = end.timestamp_millis() + i64::from(!end.timestamp_subsec_nanos().is_multiple_of(1_000_000));
+        dir.join(format!("{}_{}.json", start.timestamp_millis(), end_ms))
+    }
+
+    // The pieces of a tag whose named range overlaps or touches `start..=end`
+    fn pieces(dir: &Path, start: DateTime<Utc>, end: DateTime<Utc>) -> Vec<Piece> {
+        let Ok(files) = fs::read_dir(dir) else {
+            return vec![];
+        };
+
+        files.flatten()
+            .filter_map(|file| {
+                let name = file.file_name().into_string().ok()?;
+                let (from, to) = name.strip_suffix(".json")?.split_once('_')?;
+                let piece = Piece {
+                    path: file.path(),
+                    start: DateTime::from_timestamp_millis(from.parse().ok()?)?,
+                    end: DateTime::from_timestamp_millis(to.parse().ok()?)?,
+                };
+                (piece.end >= start && piece.start <= end).then_some(piece)
+            })
+            .collect()
+    }
+
+    fn read(path: &Path) -> Option<CacheEntry> {
+        let bytes = fs::read(path).ok()?;
+
+        match serde_json::from_slice(&bytes) {
+            Ok(entry) => {
+                // The modification time doubles as the last use for eviction
+                if let Err(e) = File::options().write(true).open(path).and_then(|f| f.set_modified(SystemTime::now())) {
+                    debug!(path = %path.display(), error = %e, "failed to touch cache file");
+                }
+                Some(entry)
+            }
+            Err(e) => {
+                warn!(path = %path.display(), error = %e, "discarding unreadable cache file");
+                let _ = fs::remove_file(path);
+                None
+            }
+        }
+    }
+
+    fn write(path: &Path, entry: &CacheEntry) -> io::Result<()> {
+        // Written aside and renamed so readers never see a partial file
+        let partial = path.with_extension("json.partial");
+
+        serde_json::to_vec(entry)
+            .map_err(io::Error::other)
+            .and_then(|bytes| fs::write(&partial, bytes))
+            .and_then(|_| fs::rename(&partial, path))
+    }
+
+    // Merges the part of a segment within one day with the pieces of that day it overlaps or
+    // touches, so no piece spans two days
+    fn insert_piece(&self, dir: &Path, tag: &Tag, day: Range, piece: Segment) -> io::Result<()> {
+        let mut entry = CacheEntry::new(tag.clone());
+        let mut read = vec![];
+
+        for stored in Self::pieces(dir, piece.start, piece.end) {
+            // Named ranges are rounded, so the exact range is checked from the content
+            let Some(stored_entry) = Self::read(&stored.path) else {
+                continue;
+            };
+            let same_day = stored_entry.segments.iter().all(|s| s.start >= day.0 && s.end <= day.1);
+            if same_day {
+                entry.segments.extend(stored_entry.segments);
+                read.push(stored.path);
+            }
+        }
+
+        entry.insert(piece);
+
+        for segment in std::mem::take(&mut entry.segments) {
+            let path = Self::piece_path(dir, segment.start, segment.end);
+            Self::write(&path, &CacheEntry { tag: tag.clone(), segments: vec![segment] })?;
+            read.retain(|p| *p != path);
+        }
+
+        for path in read {
+            fs::remove_file(path)?;
+        }
+
+        Ok(())
+    }
+
+    fn evict(&self) -> io::Result<()> {
+        let mut files = vec![];
+        for dir in fs::read_dir(&self.dir)? {
+            let dir = dir?;
+            if !dir.file_type()?.is_dir() {
+                continue;
+            }
+            for file in fs::read_dir(dir.path())? {
+                let file = file?;
+                let metadata = file.metadata()?;
+                if file.path().extension().is_some_and(|e| e == "json") {
+                    files.push((metadata.modified()?, metadata.len(), file.path()));
+                }
+            }
+        }
+
+        let mut total: u64 = files.iter().map(|(_, len, _)| len).sum();
+        files.sort();
+
+        for (_, len, path) in files {
+            if total <= self.max_bytes {
+                break;
+            }
+            debug!(path = %path.display(), bytes = len, "evicted from cache");
+            fs::remove_file(&path)?;
+            total -= len;
+
+            // Only succeeds once the tag's last piece is gone
+            if let Some(dir) = path.parent() {
+                let _ = fs::remove_dir(dir);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl CacheStore for DiskStore {
+    fn get(&self, key: &CacheKey, start: DateTime<Utc>, end: DateTime<Utc>) -> Option<CacheEntry> {
+        let mut entry: Option<CacheEntry> = None;
+
+        for piece in Self::pieces(&self.tag_dir(key), start, end) {
+            if piece.end <= start || piece.start >= end {
+                continue;
+            }
+            let Some(stored) = Self::read(&piece.path) else {
+                continue;
+            };
+
+            let entry = entry.get_or_insert_with(|| CacheEntry::new(stored.tag.clone()));
+            for segment in stored.segments {
+                entry.insert(segment);
+            }
+        }
+
+        entry.map(|entry| entry.slice(start, end))
+    }
+
+    fn insert(&self, key: &CacheKey, tag: &Tag, segment: Segment) {
+        let dir = self.tag_dir(key);
+        let _writing = self.writing.lock().unwrap();
+
+        let mut day = segment.start.duration_trunc(TimeDelta::days(1)).unwrap_or(segment.start);
+        let written = fs::create_dir_all(&dir).and_then(|_| {
+            while day < segment.end {
+                let next = day + TimeDelta::days(1);
+                self.insert_piece(&dir, tag, (day, next), segment.slice(day, next))?;
+                day = next;
+            }
+            self.evict()
+        });
+
+        if let Err(e) = written {
+            warn!(path = %dir.display(), error = %e, "failed to write cache files");
+        }
+    }
+
+    fn clear(&self) {
+        let _writing = self.writing.lock().unwrap();
+
+        if let Ok(dirs) = fs::read_dir(&self.dir) {
+            for dir in dirs.flatten().filter(|d| d.path().is_dir()) {
+                if let Ok(files) = fs::read_dir(dir.path()) {
+                    for file in files.flatten() {
+                        if file.path().extension().is_some_and(|e| e == "json") {
+                            let _ = fs::remove_file(file.path());
+                        }
+                    }
+                }
+                let _ = fs::remove_dir(dir.path());
+            }
+        }
+    }
+}
+
+/// Remembers the data of absolute time ranges fetched with [`GetDataRequest::send`], so a
+/// later request only fetches the parts not held yet.
+///
+/// Data newer than the `recent` window may still change or arrive late, so it is always
+/// fetched and never stored. Requests with a relative start or end bypass the cache.
+pub struct DataCache {
+    store: Box<dyn CacheStore>,
+    recent: TimeDelta,
+}
+
+impl DataCache {
+    pub fn new(store: impl CacheStore + 'static) -> Self {
+        Self {
+            store: Box::new(store),
+            recent: TimeDelta::minutes(15),
+        }
+    }
+
+    /// An in-memory cache holding up to `max_points` points.
+    pub fn memory(max_points: usize) -> Self {
+        Self::new(MemoryStore::new(max_points))
+    }
+
+    /// A cache in `dir` taking up to `max_bytes` of disk space.
+    pub fn disk(dir: impl Into<PathBuf>, max_bytes: u64) -> io::Result<Self> {
+        Ok(Self::new(DiskStore::new(dir, max_bytes)?))
+    }
+
+    /// Sets how far back from now data is always fetched. Defaults to 15 minutes.
+    pub fn recent(mut self, recent: TimeDelta) -> Self {
+        self.recent = recent.max(TimeDelta::zero());
+        self
+    }
+
+    pub fn clear(&self) {
+        self.store.clear();
+    }
+}
+
+impl GetDataRequest<'_> {
+    pub(super) async fn send_cached(&self, cache: &DataCache, start: DateTime<Utc>, end: DateTime<Utc>) -> Result<GetDataResponse, TimebaseError> {
+        if start >= end {
+            return Err(TimebaseError::InvalidTime(format!("Start {} is not before end {}", start, end)));
+        }
+
+        let limit = (Utc::now() - cache.recent).clamp(start, end);
+
+        let keys: Vec<CacheKey> = self.tag_names.iter().map(|tag| CacheKey::new(&self.dataset_name, tag)).collect();
+        let mut entries: Vec<Option<CacheEntry>> = keys.iter()
+            .map(|key| if start < limit { cache.store.get(key, start, limit) } else { None })
+            .collect();
+
+        // Tags missing the same range are fetched together
+        let mut ranges: Vec<(Range, Vec<usize>)> = vec![];
+        for (index, entry) in entries.iter().enumerate() {
+            let mut missing = match entry {
+                Some(entry) => entry.missing(start, limit),
+                None if start < limit => vec![(start, limit)],
+                None => vec![],
+            };
+            if limit < end {
+                missing.push((limit, end));
+            }
+
+            for range in missing {
+                match ranges.iter_mut().find(|(r, _)| *r == range) {
+                    Some((_, tags)) => tags.push(index),
+                    None => ranges.push((range, vec![index])),
+                }
+            }
+        }
+
+        let requests = ranges.iter().map(|((from, to), tags)| self.fetch_range(*from, *to, tags)).collect();
+        let fetched: Vec<GetDataResponse> = try_buffered(requests, self.max_concurrency).await?;
+
+        let mut tags = vec![];
+        let mut cached_points = 0;
+
+        for (index, key) in keys.iter().enumerate() {
+            let name = &self.tag_names[index];
+            let pieces: Vec<(DateTime<Utc>, DateTime<Utc>, &TagItem)> = ranges.iter().zip(&fetched)
+                .filter(|((_, tags), _)| tags.contains(&index))
+                .filter_map(|(((from, to), _), response)| {
+                    let item = response.tags.iter().find(|item| item.tag.name.eq_ignore_ascii_case(name))?;
+                    Some((*from, *to, item))
+                })
+                .collect();
+
+            let entry = &mut entries[index];
+
+            for (from, to, item) in pieces.iter().filter(|(from, _, _)| *from < limit) {
+                let segment = Segment {
+                    start: *from,
+                    end: (*to).min(limit),
+                    data: item.data.iter().filter(|d| d.timestamp <= limit).cloned().collect(),
+                };
+                cache.store.insert(key, &item.tag, segment.clone());
+
+                let entry = entry.get_or_insert_with(|| CacheEntry::new(item.tag.clone()));
+                entry.tag = item.tag.clone();
+                entry.insert(segment);
+            }
+
+            let mut data: Vec<TagData> = vec![];
+            if let Some(entry) = entry.as_ref()
+                && start < limit
+            {
+                data.extend_from_slice(entry.points_in(start, limit));
+                cached_points += data.len();
+            }
+
+            // The recent part repeats the value in effect at `limit`, already taken from the cache
+            for (_, _, item) in pieces.iter().filter(|(from, _, _)| *from >= limit) {
+                let last = data.last().map(|d| d.timestamp);
+                data.extend(item.data.iter().filter(|d| Some(d.timestamp) > last).cloned());
+            }
+
+            let tag = match (entry, pieces.first()) {
+                (Some(entry), _) => entry.tag.clone(),
+                (None, Some((_, _, item))) => item.tag.clone(),
+                (None, None) => continue,
+            };
+
+            tags.push(TagItem { tag, data });
+        }
+
+        info!(ranges = ranges.len(), cached_points, "data served from cache");
+
+        Ok(GetDataResponse { start, end, tags })
+    }
+
+    // Fetches some of the tags over part of the range, chunked like the original request
+    async fn fetch_range(&self, from: DateTime<Utc>, to: DateTime<Utc>, tags: &[usize]) -> Result<GetDataResponse, TimebaseError> {
+        let mut builder = self.client.get_data(&self.dataset_name)
+            .start(from)
+            .end(to)
+            .max_concurrency(self.max_concurrency);
+        for index in tags {
+            builder = builder.tag_name(&self.tag_names[*index]);
+        }
+        builder.chunking = self.chunking;
+
+        // Windows shorter than a chunk are fetched whole
+        if self.chunking.is_some_and(|c| c.window().is_ok_and(|window| to - from <= window)) {
+            builder.chunking = None;
+        }
+
+        builder.build()?.fetch_all().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timebase::TagValue;
+    use std::sync::Arc;
+    use std::thread;
+
+    // Seconds after midnight on 2024-01-01, so tests can cross day boundaries
+    fn at(seconds: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(1_704_067_200 + seconds, 0).unwrap()
+    }
+
+    fn tag() -> Tag {
+        Tag { name: "Flow".into(), description: None, format: None, uom: None, fields: None, data_type: None }
+    }
+
+    fn point(seconds: i64, value: f64) -> TagData {
+        TagData { timestamp: at(seconds), value: Some(TagValue::Float(value)), quality: 192 }
+    }
+
+    fn segment(start: i64, end: i64, points: &[(i64, f64)]) -> Segment {
+        Segment { start: at(start), end: at(end), data: points.iter().map(|(t, v)| point(*t, *v)).collect() }
+    }
+
+    fn ranges(entry: &CacheEntry) -> Vec<(i64, i64)> {
+        entry.segments.iter().map(|s| (s.start.timestamp() - at(0).timestamp(), s.end.timestamp() - at(0).timestamp())).collect()
+    }
+
+    fn values(data: &[TagData]) -> Vec<f64> {
+        data.iter().map(|d| match d.value {
+            Some(TagValue::Float(v)) => v,
+            _ => f64::NAN,
+        }).collect()
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("timebase-cache-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn missing_lists_uncovered_ranges() {
+        let mut entry = CacheEntry::new(tag());
+        assert_eq!(entry.missing(at(0), at(100)), [(at(0), at(100))]);
+
+        entry.insert(segment(10, 20, &[]));
+        entry.insert(segment(40, 60, &[]));
+
+        assert_eq!(entry.missing(at(0), at(100)), [(at(0), at(10)), (at(20), at(40)), (at(60), at(100))]);
+        assert_eq!(entry.missing(at(15), at(50)), [(at(20), at(40))]);
+        assert!(entry.missing(at(45), at(55)).is_empty());
+    }
+
+    #[test]
+    fn insert_merges_overlapping_and_touching_segments() {
+        let mut entry = CacheEntry::new(tag());
+        entry.insert(segment(30, 40, &[(30, 3.0)]));
+        entry.insert(segment(0, 10, &[(0, 1.0)]));
+        entry.insert(segment(10, 20, &[(10, 2.0)]));
+        assert_eq!(ranges(&entry), [(0, 20), (30, 40)]);
+
+        entry.insert(segment(15, 35, &[(15, 2.5)]));
+        assert_eq!(ranges(&entry), [(0, 40)]);
+        assert_eq!(values(&entry.segments[0].data), [1.0, 2.0, 2.5, 3.0]);
+    }
+
+    #[test]
+    fn merge_points_keeps_the_newer_duplicate() {
+        let old = vec![point(0, 1.0), point(10, 2.0), point(20, 3.0)];
+        let new = vec![point(10, 20.0), point(15, 25.0)];
+
+        assert_eq!(values(&merge_points(old, new)), [1.0, 20.0, 25.0, 3.0]);
+    }
+
+    #[test]
+    fn points_in_starts_with_the_value_in_effect() {
+        let mut entry = CacheEntry::new(tag());
+        entry.insert(segment(0, 100, &[(0, 1.0), (10, 2.0), (20, 3.0), (30, 4.0)]));
+
+        assert_eq!(values(entry.points_in(at(15), at(20))), [2.0, 3.0]);
+        assert_eq!(values(entry.points_in(at(10), at(25))), [2.0, 3.0]);
+        assert_eq!(values(entry.points_in(at(40), at(50))), [4.0]);
+
+        // Not covered by one segment
+        assert!(entry.points_in(at(50), at(150)).is_empty());
+    }
+
+    #[test]
+    fn slice_cuts_segments_to_the_range() {
+        let mut entry = CacheEntry::new(tag());
+        entry.insert(segment(0, 30, &[(0, 1.0), (10, 2.0), (20, 3.0)]));
+        entry.insert(segment(50, 80, &[(50, 5.0), (70, 7.0)]));
+
+        let slice = entry.slice(at(15), at(60));
+        assert_eq!(ranges(&slice), [(15, 30), (50, 60)]);
+        assert_eq!(values(&slice.segments[0].data), [2.0, 3.0]);
+        assert_eq!(values(&slice.segments[1].data), [5.0]);
+
+        // Segments only touching the range are left out
+        assert!(entry.slice(at(30), at(50)).segments.is_empty());
+    }
+
+    #[test]
+    fn memory_store_evicts_least_recently_used() {
+        let store = MemoryStore::new(3);
+        let (a, b) = (CacheKey::new("Plant", "A"), CacheKey::new("Plant", "B"));
+
+        store.insert(&a, &tag(), segment(0, 10, &[(0, 1.0), (5, 2.0)]));
+        store.insert(&b, &tag(), segment(0, 10, &[(0, 1.0)]));
+        assert!(store.get(&a, at(0), at(10)).is_some());
+
+        // A was used last, so B goes
+        store.insert(&a, &tag(), segment(10, 20, &[(10, 3.0)]));
+        assert!(store.get(&b, at(0), at(10)).is_none());
+        assert_eq!(store.get(&a, at(0), at(20)).map(|e| e.points()), Some(3));
+    }
+
+    #[test]
+    fn disk_store_splits_segments_by_day() {
+        let dir = temp_dir("days");
+        let store = DiskStore::new(&dir, u64::MAX).unwrap();
+        let key = CacheKey::new("Plant", "Flow");
+        let day = 86_400;
+
+        store.insert(&key, &tag(), segment(day - 20, day + 20, &[(day - 20, 1.0), (day - 10, 2.0), (day + 10, 3.0)]));
+        store.insert(&key, &tag(), segment(day + 20, day + 30, &[(day + 20, 4.0)]));
+        assert_eq!(fs::read_dir(store.tag_dir(&key)).unwrap().count(), 2);
+
+        let entry = store.get(&key, at(day - 30), at(day + 40)).unwrap();
+        assert_eq!(ranges(&entry), [(day - 20, day + 30)]);
+        assert_eq!(values(&entry.segments[0].data), [1.0, 2.0, 3.0, 4.0]);
+
+        // Only the second day is read, starting with the value in effect at midnight
+        let entry = DiskStore::new(&dir, u64::MAX).unwrap().get(&key, at(day + 5), at(day + 40)).unwrap();
+        assert_eq!(ranges(&entry), [(day + 5, day + 30)]);
+        assert_eq!(values(&entry.segments[0].data), [2.0, 3.0, 4.0]);
+
+        store.clear();
+        assert!(store.get(&key, at(0), at(2 * day)).is_none());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    // Threads each add their own segments to one tag; every one of them must be kept
+    fn concurrent_inserts(store: Arc<dyn CacheStore>) {
+        let key = CacheKey::new("Plant", "Flow");
+
+        let threads: Vec<_> = (0..8).map(|thread| {
+            let (store, key) = (store.clone(), key.clone());
+            thread::spawn(move || {
+                for i in 0..10 {
+                    let start = (i * 8 + thread) * 20;
+                    store.insert(&key, &tag(), segment(start, start + 10, &[(start, start as f64)]));
+                }
+            })
+        }).collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        let entry = store.get(&key, at(0), at(2000)).unwrap();
+        assert_eq!(entry.segments.len(), 80);
+        assert_eq!(entry.points(), 80);
+    }
+
+    #[test]
+    fn concurrent_inserts_keep_every_segment() {
+        concurrent_inserts(Arc::new(MemoryStore::new(usize::MAX)));
+
+        let dir = temp_dir("concurrent");
+        concurrent_inserts(Arc::new(DiskStore::new(&dir, u64::MAX).unwrap()));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
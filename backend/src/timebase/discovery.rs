@@ -0,0 +1,170 @@
+use super::{read_json, Exchange, Tag, TimebaseClient, TimebaseError};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, instrument};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Dataset {
+    #[serde(rename = "n")]
+    pub name: String,
+
+    #[serde(rename = "d")]
+    pub description: Option<String>,
+}
+
+impl TimebaseClient {
+    /// Lists the datasets available on the historian.
+    #[instrument(skip(self))]
+    pub async fn list_datasets(&self) -> Result<Vec<Dataset>, TimebaseError> {
+        let url = self.endpoint(&["api", "datasets"])?;
+        let resp = self.execute(self.http.get(url), Exchange::Buffered).await?;
+
+        read_json(resp).await
+    }
+
+    /// Starts a request listing the tags of a dataset, optionally filtered by name.
+    pub fn list_tags<'a>(&'a self, dataset: &'a str) -> ListTagsRequestBuilder<'a> {
+        ListTagsRequestBuilder {
+            client: self,
+            dataset_name: dataset,
+            pattern: None,
+            prefix: None,
+        }
+    }
+
+    /// Returns the metadata of a single tag.
+    #[instrument(skip(self))]
+    pub async fn get_tag_info(&self, dataset: &str, tag: &str) -> Result<Tag, TimebaseError> {
+        let url = self.endpoint(&["api", "datasets", dataset, "tags", tag])?;
+        let resp = self.execute(self.http.get(url), Exchange::Buffered).await?;
+
+        if resp.status() == StatusCode::NOT_FOUND {
+            return Err(TimebaseError::UnknownTag {
+                dataset: dataset.to_string(),
+                tag: tag.to_string(),
+            });
+        }
+
+        read_json(resp).await
+    }
+}
+
+pub struct ListTagsRequestBuilder<'a> {
+    client: &'a TimebaseClient,
+    dataset_name: &'a str,
+    pattern: Option<&'a str>,
+    prefix: Option<&'a str>,
+}
+
+impl<'a> ListTagsRequestBuilder<'a> {
+    /// Only returns tags matching a wildcard pattern, where `*` matches any run of characters
+    /// and `?` matches a single character, e.g. `131-F?-*`. Matching ignores case.
+    pub fn filter(mut self, pattern: &'a str) -> Self {
+        self.pattern = Some(pattern);
+        self
+    }
+
+    /// Only returns tags whose name starts with `prefix`, e.g. `FL001.`. Matching ignores case.
+    pub fn prefix(mut self, prefix: &'a str) -> Self {
+        self.prefix = Some(prefix);
+        self
+    }
+
+    #[instrument(name = "list_tags", skip(self), fields(dataset = self.dataset_name, filter = self.pattern, prefix = self.prefix))]
+    pub async fn send(self) -> Result<Vec<Tag>, TimebaseError> {
+        let url = self.client.endpoint(&["api", "datasets", self.dataset_name, "tags"])?;
+        let resp = self.client.execute(self.client.http.get(url), Exchange::Buffered).await?;
+
+        if resp.status() == StatusCode::NOT_FOUND {
+            return Err(TimebaseError::UnknownDataset(self.dataset_name.to_string()));
+        }
+
+        let tags: Vec<Tag> = read_json(resp).await?;
+        let total = tags.len();
+
+        let tags: Vec<Tag> = tags
+            .into_iter()
+            .filter(|tag| self.matches(&tag.name))
+            .collect();
+
+        debug!(total, matched = tags.len(), "tags listed");
+
+        Ok(tags)
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        let name = name.to_lowercase();
+
+        if let Some(prefix) = self.prefix
+            && !name.starts_with(&prefix.to_lowercase())
+        {
+            return false;
+        }
+
+        match self.pattern {
+            Some(pattern) => wildcard_match(&pattern.to_lowercase(), &name),
+            None => true,
+        }
+    }
+}
+
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+
+    let (mut p, mut t) = (0, 0);
+    // Position of the last `*` seen and the text position it was matched against
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((star, matched)) = backtrack {
+            // Let the last `*` swallow one more character and retry
+            p = star + 1;
+            t = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wildcards() {
+        assert!(wildcard_match("131-ft-001.pv", "131-ft-001.pv"));
+        assert!(wildcard_match("*", ""));
+        assert!(wildcard_match("*", "131-ft-001.pv"));
+        assert!(wildcard_match("131-*", "131-ft-001.pv"));
+        assert!(wildcard_match("*.pv", "131-ft-001.pv"));
+        assert!(wildcard_match("131-??-001*", "131-ft-001.pv"));
+        assert!(wildcard_match("*ft*pv", "131-ft-001.pv"));
+        // The `*` has to give back characters to a later literal
+        assert!(wildcard_match("*a*ab", "aaab"));
+
+        assert!(!wildcard_match("131-ft-001", "131-ft-001.pv"));
+        assert!(!wildcard_match("*.sp", "131-ft-001.pv"));
+        assert!(!wildcard_match("131-?-001*", "131-ft-001.pv"));
+        assert!(!wildcard_match("?", ""));
+    }
+
+    #[test]
+    fn filters_ignore_case() {
+        let client = TimebaseClient::default();
+        let request = client.list_tags("ds").prefix("131-").filter("*-FT-*.pv");
+
+        assert!(request.matches("131-ft-001.PV"));
+        assert!(!request.matches("132-FT-001.PV"));
+        assert!(!request.matches("131-TT-001.PV"));
+    }
+}
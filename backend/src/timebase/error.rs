@@ -0,0 +1,79 @@
+use reqwest::{StatusCode, Url};
+use std::time::Duration;
+use thiserror::Error;
+
+/// Errors returned by the Timebase client.
+#[derive(Error, Debug)]
+pub enum TimebaseError {
+    /// The base URL, host, scheme or port could not be used to build a request URL.
+    #[error("invalid URL: {0}")]
+    InvalidUrl(String),
+
+    /// A client setting such as a header or proxy could not be applied.
+    #[error("invalid configuration: {0}")]
+    Configuration(String),
+
+    /// Credentials could not be obtained, e.g. a token provider failed.
+    #[error("authentication failed: {0}")]
+    Authentication(String),
+
+    /// A start/end time or relative time expression was rejected before sending.
+    #[error("invalid time: {0}")]
+    InvalidTime(String),
+
+    /// The historian could not be reached, or the request timed out.
+    #[error("transport error: {0}")]
+    Transport(#[from] reqwest::Error),
+
+    /// The historian answered with a non-success status code.
+    #[error("HTTP request to {url} failed with status code {status}: {body}")]
+    Status {
+        url: Url,
+        status: StatusCode,
+        body: String,
+    },
+
+    /// The response body was not the JSON we expected.
+    #[error("failed to decode response at `{path}`: {source}")]
+    Decode {
+        path: String,
+        #[source]
+        source: serde_json::Error,
+    },
+
+    /// A streamed response delivered no data within the client timeout.
+    #[error("response stalled for {0:?}")]
+    Stalled(Duration),
+
+    #[error("unknown dataset \"{0}\"")]
+    UnknownDataset(String),
+
+    #[error("unknown tag \"{tag}\" in dataset \"{dataset}\"")]
+    UnknownTag { dataset: String, tag: String },
+}
+
+impl TimebaseError {
+    /// Returns true if the request timed out.
+    pub fn is_timeout(&self) -> bool {
+        match self {
+            TimebaseError::Transport(e) => e.is_timeout(),
+            TimebaseError::Stalled(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Returns true for failures that may succeed if the request is sent again, such as
+    /// connection errors, timeouts and 5xx responses. Unknown datasets/tags, invalid input
+    /// and decode failures are permanent.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            TimebaseError::Transport(_) | TimebaseError::Stalled(_) => true,
+            TimebaseError::Status { status, .. } => {
+                status.is_server_error()
+                    || *status == StatusCode::TOO_MANY_REQUESTS
+                    || *status == StatusCode::REQUEST_TIMEOUT
+            }
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,244 @@
+use super::TimebaseError;
+use rand::Rng;
+use reqwest::StatusCode;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+type RetryHook = Arc<dyn Fn(&RetryEvent) + Send + Sync>;
+
+/// Details of a failed attempt that is about to be retried.
+pub struct RetryEvent<'a> {
+    /// The attempt that failed, starting at 1.
+    pub attempt: u32,
+    /// How long the client waits before the next attempt.
+    pub delay: Duration,
+    pub error: &'a TimebaseError,
+}
+
+/// Decides which failed requests a [`super::TimebaseClient`] sends again and how long it
+/// waits in between. The delay doubles (by default) after every attempt, is capped at
+/// `max_backoff` and is randomized by up to `jitter` in either direction so that many
+/// clients do not retry in lockstep.
+#[derive(Clone)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    multiplier: f64,
+    jitter: f64,
+    retry_statuses: Vec<StatusCode>,
+    retry_connect_errors: bool,
+    retry_timeouts: bool,
+    retry_writes: bool,
+    on_retry: Option<RetryHook>,
+}
+
+impl Default for RetryPolicy {
+    /// Three attempts starting at 250 ms, retrying connect errors, timeouts and 502/503/504.
+    /// Writes are sent once.
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(250),
+            max_backoff: Duration::from_secs(10),
+            multiplier: 2.0,
+            jitter: 0.2,
+            retry_statuses: vec![StatusCode::BAD_GATEWAY, StatusCode::SERVICE_UNAVAILABLE, StatusCode::GATEWAY_TIMEOUT],
+            retry_connect_errors: true,
+            retry_timeouts: true,
+            retry_writes: false,
+            on_retry: None,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy that sends every request exactly once.
+    pub fn none() -> Self {
+        Self::default().max_attempts(1)
+    }
+
+    /// Sets the total number of attempts, including the first one.
+    pub fn max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// Sets the delay before the first retry and the upper limit for later delays.
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max.max(initial);
+        self
+    }
+
+    /// Sets the factor the delay grows by after each attempt.
+    pub fn multiplier(mut self, multiplier: f64) -> Self {
+        self.multiplier = multiplier.max(1.0);
+        self
+    }
+
+    /// Sets the random variation of each delay as a fraction of it, between 0 and 1.
+    pub fn jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Sets the HTTP status codes that are retried.
+    pub fn retry_statuses(mut self, statuses: &[StatusCode]) -> Self {
+        self.retry_statuses = statuses.to_vec();
+        self
+    }
+
+    pub fn retry_connect_errors(mut self, retry: bool) -> Self {
+        self.retry_connect_errors = retry;
+        self
+    }
+
+    pub fn retry_timeouts(mut self, retry: bool) -> Self {
+        self.retry_timeouts = retry;
+        self
+    }
+
+    /// Sets whether writes are retried as well. Off by default: a write whose response was
+    /// lost may already have been stored, and sending it again stores its points twice.
+    pub fn retry_writes(mut self, retry: bool) -> Self {
+        self.retry_writes = retry;
+        self
+    }
+
+    /// Registers a callback that is told about every retry, e.g. to log it.
+    pub fn on_retry<F: Fn(&RetryEvent) + Send + Sync + 'static>(mut self, on_retry: F) -> Self {
+        self.on_retry = Some(Arc::new(on_retry));
+        self
+    }
+
+    pub(super) fn attempts(&self, write: bool) -> u32 {
+        match write && !self.retry_writes {
+            true => 1,
+            false => self.max_attempts,
+        }
+    }
+
+    pub(super) fn retries_status(&self, status: StatusCode) -> bool {
+        !status.is_success() && self.retry_statuses.contains(&status)
+    }
+
+    pub(super) fn retries_error(&self, error: &TimebaseError) -> bool {
+        match error {
+            TimebaseError::Transport(e) if e.is_timeout() => self.retry_timeouts,
+            TimebaseError::Stalled(_) => self.retry_timeouts,
+            TimebaseError::Transport(e) if e.is_connect() => self.retry_connect_errors,
+            TimebaseError::Status { status, .. } => self.retries_status(*status),
+            _ => false,
+        }
+    }
+
+    pub(super) fn delay(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(i32::MAX as u32) as i32;
+        let base = (self.initial_backoff.as_secs_f64() * self.multiplier.powi(exponent))
+            .min(self.max_backoff.as_secs_f64());
+
+        let factor = match self.jitter {
+            0.0 => 1.0,
+            jitter => rand::rng().random_range(1.0 - jitter..=1.0 + jitter),
+        };
+
+        Duration::from_secs_f64(base * factor)
+    }
+
+    pub(super) fn report(&self, event: &RetryEvent) {
+        if let Some(on_retry) = &self.on_retry {
+            on_retry(event);
+        }
+    }
+}
+
+impl fmt::Debug for RetryPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RetryPolicy")
+            .field("max_attempts", &self.max_attempts)
+            .field("initial_backoff", &self.initial_backoff)
+            .field("max_backoff", &self.max_backoff)
+            .field("multiplier", &self.multiplier)
+            .field("jitter", &self.jitter)
+            .field("retry_statuses", &self.retry_statuses)
+            .field("retry_connect_errors", &self.retry_connect_errors)
+            .field("retry_timeouts", &self.retry_timeouts)
+            .field("retry_writes", &self.retry_writes)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delay_grows_up_to_the_cap() {
+        let policy = RetryPolicy::default()
+            .backoff(Duration::from_millis(100), Duration::from_secs(1))
+            .jitter(0.0);
+
+        let delays: Vec<_> = (1..=6).map(|attempt| policy.delay(attempt).as_millis()).collect();
+        assert_eq!(delays, [100, 200, 400, 800, 1000, 1000]);
+
+        // Exponents far beyond the cap must not overflow
+        assert_eq!(policy.delay(u32::MAX), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn jitter_stays_within_its_bounds() {
+        let policy = RetryPolicy::default()
+            .backoff(Duration::from_millis(100), Duration::from_millis(400))
+            .jitter(0.5);
+
+        let first: Vec<_> = (0..200).map(|_| policy.delay(1)).collect();
+        assert!(first.iter().all(|d| (Duration::from_millis(50)..=Duration::from_millis(150)).contains(d)));
+        assert!(first.iter().any(|d| *d != first[0]));
+
+        // Jitter applies to the capped delay
+        assert!((0..200).map(|_| policy.delay(10)).all(|d| (Duration::from_millis(200)..=Duration::from_millis(600)).contains(&d)));
+    }
+
+    #[test]
+    fn retries_statuses_and_stalls() {
+        let policy = RetryPolicy::default();
+        let status = |status| TimebaseError::Status {
+            url: "http://localhost:4511/api".parse().unwrap(),
+            status,
+            body: String::new(),
+        };
+
+        assert!(policy.retries_error(&status(StatusCode::SERVICE_UNAVAILABLE)));
+        assert!(!policy.retries_error(&status(StatusCode::INTERNAL_SERVER_ERROR)));
+        assert!(!policy.retries_error(&status(StatusCode::NOT_FOUND)));
+        assert!(policy.retries_error(&TimebaseError::Stalled(Duration::from_secs(1))));
+        assert!(!policy.retries_error(&TimebaseError::InvalidTime("*-x".into())));
+        assert!(!policy.retries_error(&TimebaseError::UnknownDataset("ds".into())));
+
+        let policy = policy.retry_timeouts(false).retry_statuses(&[StatusCode::INTERNAL_SERVER_ERROR]);
+        assert!(!policy.retries_error(&TimebaseError::Stalled(Duration::from_secs(1))));
+        assert!(policy.retries_error(&status(StatusCode::INTERNAL_SERVER_ERROR)));
+        assert!(!policy.retries_error(&status(StatusCode::SERVICE_UNAVAILABLE)));
+    }
+
+    #[tokio::test]
+    async fn retries_connect_errors() {
+        // Nothing listens on the port of a listener that has been dropped
+        let address = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let error = TimebaseError::from(reqwest::get(format!("http://{}", address)).await.unwrap_err());
+
+        assert!(RetryPolicy::default().retries_error(&error));
+        assert!(!RetryPolicy::default().retry_connect_errors(false).retries_error(&error));
+    }
+
+    #[test]
+    fn writes_are_sent_once_unless_enabled() {
+        let policy = RetryPolicy::default().max_attempts(4);
+
+        assert_eq!(policy.attempts(false), 4);
+        assert_eq!(policy.attempts(true), 1);
+        assert_eq!(policy.retry_writes(true).attempts(true), 4);
+    }
+}
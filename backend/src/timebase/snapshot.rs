@@ -0,0 +1,55 @@
+use super::{TimebaseClient, TimebaseError};
+use crate::timeseries::{DataPoint, DataSeries, Tag};
+use chrono::TimeDelta;
+use serde::{Serialize, Serializer};
+use tracing::instrument;
+
+/// The current value of a tag.
+#[derive(Debug, Clone, Serialize)]
+pub struct Snapshot {
+    pub tag: Tag,
+
+    /// The most recent point, or `None` if the tag has never had a value.
+    pub point: Option<DataPoint>,
+
+    /// Time since the point was recorded, by the historian's clock.
+    #[serde(rename = "age_seconds", serialize_with = "seconds")]
+    pub age: Option<TimeDelta>,
+}
+
+impl TimebaseClient {
+    /// Returns the latest point of each tag, in the order the tags were given.
+    ///
+    /// Timebase repeats the value in effect at the start of every range, so an empty range at
+    /// `*` (now) returns exactly one point per tag however often the tags are sampled.
+    #[instrument(skip(self))]
+    pub async fn get_snapshot(&self, dataset: &str, tags: &[&str]) -> Result<Vec<Snapshot>, TimebaseError> {
+        let mut request = self.get_data(dataset);
+        for tag in tags {
+            request = request.tag_name(tag);
+        }
+
+        let response = request.relative_start("*").relative_end("*").build()?.send().await?;
+        let now = response.end;
+        let mut series = response.time_series();
+
+        // send() has already checked that every requested tag is in the response
+        let snapshots = tags.iter().filter_map(|name| {
+            let index = series.iter().position(|s| s.tag.name.eq_ignore_ascii_case(name))?;
+            let DataSeries { tag, mut data } = series.swap_remove(index);
+            let point = data.pop();
+
+            Some(Snapshot {
+                age: point.as_ref().map(|p| now - p.timestamp),
+                tag,
+                point,
+            })
+        }).collect();
+
+        Ok(snapshots)
+    }
+}
+
+fn seconds<S: Serializer>(age: &Option<TimeDelta>, serializer: S) -> Result<S::Ok, S::Error> {
+    age.map(|age| age.as_seconds_f64()).serialize(serializer)
+}
@@ -0,0 +1,517 @@
+use super::{Exchange, GetDataRequest, Tag, TagData, TimebaseError};
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use futures::stream::BoxStream;
+use futures::{Stream, StreamExt};
+use serde::de::DeserializeOwned;
+use std::collections::{HashMap, VecDeque};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::{debug, info, Instrument, Span};
+
+/// Stream of data points returned by [`GetDataRequest::send_stream`].
+pub type DataPointStream<'a> = Pin<Box<dyn Stream<Item = Result<(Arc<Tag>, TagData), TimebaseError>> + Send + 'a>>;
+
+impl<'a> GetDataRequest<'a> {
+    /// Sends the request and yields each data point as soon as it has been parsed, without
+    /// buffering the whole response. Points arrive grouped by tag in the order Timebase
+    /// returns them. Chunked requests fetch their windows one after another. The stream may
+    /// run for longer than the client timeout, but fails once no data arrives within it.
+    pub fn send_stream(&'a self) -> DataPointStream<'a> {
+        let state = StreamState {
+            request: self,
+            next_url: 0,
+            body: None,
+            parser: ResponseParser::default(),
+            ready: VecDeque::new(),
+            seen: vec![],
+            last_window: HashMap::new(),
+            last_point: HashMap::new(),
+            span: self.span("stream"),
+            started: Instant::now(),
+            points: 0,
+        };
+
+        Box::pin(futures::stream::try_unfold(state, |mut state| async move {
+            let span = state.span.clone();
+            let item = state.next().instrument(span).await?;
+            Ok(item.map(|item| (item, state)))
+        }))
+    }
+}
+
+struct StreamState<'a> {
+    request: &'a GetDataRequest<'a>,
+    next_url: usize,
+    body: Option<BoxStream<'static, reqwest::Result<Bytes>>>,
+    parser: ResponseParser,
+    ready: VecDeque<(Arc<Tag>, TagData)>,
+    seen: Vec<String>,
+    // Last timestamp per tag from earlier chunk windows, used to drop repeated boundary points
+    last_window: HashMap<String, DateTime<Utc>>,
+    last_point: HashMap<String, DateTime<Utc>>,
+    span: Span,
+    started: Instant,
+    points: usize,
+}
+
+impl StreamState<'_> {
+    async fn next(&mut self) -> Result<Option<(Arc<Tag>, TagData)>, TimebaseError> {
+        loop {
+            while let Some((tag, data)) = self.ready.pop_front() {
+                if self.last_window.get(&tag.name).is_some_and(|last| data.timestamp <= *last) {
+                    continue;
+                }
+
+                self.last_point.insert(tag.name.clone(), data.timestamp);
+                self.points += 1;
+                return Ok(Some((tag, data)));
+            }
+
+            let Some(body) = self.body.as_mut() else {
+                match self.request.urls.get(self.next_url) {
+                    Some(url) => {
+                        let resp = self.request.open(url, Exchange::Streamed).await?;
+                        self.body = Some(resp.bytes_stream().boxed());
+                        self.parser = ResponseParser::default();
+                        self.next_url += 1;
+                        continue;
+                    }
+                    None => {
+                        self.request.check_tags(self.seen.iter().map(String::as_str))?;
+                        info!(
+                            points = self.points,
+                            latency_ms = self.started.elapsed().as_millis() as u64,
+                            "data streamed"
+                        );
+                        return Ok(None);
+                    }
+                }
+            };
+
+            let timeout = self.request.client.timeout;
+            let Ok(chunk) = tokio::time::timeout(timeout, body.next()).await else {
+                return Err(TimebaseError::Stalled(timeout));
+            };
+
+            match chunk {
+                Some(chunk) => self.ready.extend(self.parser.feed(&chunk?)?),
+                None => {
+                    self.parser.finish()?;
+                    debug!(window = self.next_url, points = self.points, "window streamed");
+                    self.seen.append(&mut self.parser.tag_names);
+                    self.last_window.extend(self.last_point.drain());
+                    self.body = None;
+                }
+            }
+        }
+    }
+}
+
+enum Frame {
+    Object { key: Option<String>, expect_key: bool },
+    Array,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum CaptureKind {
+    Tag,
+    Data,
+}
+
+struct Capture {
+    kind: CaptureKind,
+    start: usize,
+    depth: usize,
+}
+
+// Incremental scanner for the `{"s": .., "e": .., "tl": [{"t": {..}, "d": [{..}, ..]}, ..]}`
+// response layout. It tracks just enough JSON structure to cut out each complete `t` and
+// `d` element and hands those to serde, so only the element being parsed is buffered.
+#[derive(Default)]
+struct ResponseParser {
+    buf: Vec<u8>,
+    pos: usize,
+    stack: Vec<Frame>,
+    in_string: bool,
+    in_key: bool,
+    escape: bool,
+    in_scalar: bool,
+    string_start: usize,
+    capture: Option<Capture>,
+    done: bool,
+    item_index: usize,
+    data_index: usize,
+    tag: Option<Arc<Tag>>,
+    // Points that appeared before their item's `t` member
+    pending: Vec<TagData>,
+    tag_names: Vec<String>,
+    out: Vec<(Arc<Tag>, TagData)>,
+}
+
+impl ResponseParser {
+    fn feed(&mut self, chunk: &[u8]) -> Result<Vec<(Arc<Tag>, TagData)>, TimebaseError> {
+        self.buf.extend_from_slice(chunk);
+
+        while self.pos < self.buf.len() {
+            self.step(self.buf[self.pos])?;
+            self.pos += 1;
+        }
+
+        // Drop everything that is no longer needed to finish the current key or capture
+        let keep_from = match (&self.capture, self.in_key) {
+            (Some(capture), _) => capture.start,
+            (None, true) => self.string_start,
+            (None, false) => self.pos,
+        };
+        self.buf.drain(..keep_from);
+        self.pos -= keep_from;
+        self.string_start = self.string_start.saturating_sub(keep_from);
+        if let Some(capture) = self.capture.as_mut() {
+            capture.start -= keep_from;
+        }
+
+        Ok(std::mem::take(&mut self.out))
+    }
+
+    fn finish(&mut self) -> Result<(), TimebaseError> {
+        if self.in_scalar && self.stack.is_empty() {
+            self.done = true;
+        }
+
+        match self.done {
+            true => Ok(()),
+            false => Err(decode_error(".", "response ended before the JSON document was complete")),
+        }
+    }
+
+    fn step(&mut self, b: u8) -> Result<(), TimebaseError> {
+        if self.in_string {
+            if self.escape {
+                self.escape = false;
+            } else if b == b'\\' {
+                self.escape = true;
+            } else if b == b'"' {
+                self.in_string = false;
+
+                if self.in_key {
+                    self.in_key = false;
+                    let key = decode::<String>(&self.buf[self.string_start..=self.pos], "")?;
+                    if let Some(Frame::Object { key: current, expect_key }) = self.stack.last_mut() {
+                        *current = Some(key);
+                        *expect_key = false;
+                    }
+                } else {
+                    self.value_finished(self.pos + 1)?;
+                }
+            }
+            return Ok(());
+        }
+
+        if self.in_scalar {
+            if !matches!(b, b',' | b'}' | b']') && !b.is_ascii_whitespace() {
+                return Ok(());
+            }
+            self.in_scalar = false;
+            self.value_finished(self.pos)?;
+        }
+
+        match b {
+            b'"' => {
+                self.in_string = true;
+                self.string_start = self.pos;
+                self.in_key = matches!(self.stack.last(), Some(Frame::Object { expect_key: true, .. }));
+                if !self.in_key {
+                    self.value_started();
+                }
+            }
+            b'{' => {
+                self.value_started();
+                if self.is_item_frame(self.stack.len() + 1) {
+                    self.tag = None;
+                    self.data_index = 0;
+                }
+                self.stack.push(Frame::Object { key: None, expect_key: true });
+            }
+            b'[' => {
+                self.value_started();
+                self.stack.push(Frame::Array);
+            }
+            b'}' | b']' => {
+                if b == b'}' && self.is_item_frame(self.stack.len()) {
+                    self.item_finished()?;
+                }
+                self.stack.pop();
+                self.value_finished(self.pos + 1)?;
+                if self.stack.is_empty() {
+                    self.done = true;
+                }
+            }
+            b',' => {
+                if let Some(Frame::Object { expect_key, .. }) = self.stack.last_mut() {
+                    *expect_key = true;
+                }
+            }
+            b':' => {}
+            b if b.is_ascii_whitespace() => {}
+            _ => {
+                self.value_started();
+                self.in_scalar = true;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn key_at(&self, depth: usize) -> Option<&str> {
+        match self.stack.get(depth) {
+            Some(Frame::Object { key: Some(key), .. }) => Some(key.as_str()),
+            _ => None,
+        }
+    }
+
+    // True if an object at this stack depth (1-based) is an element of the `tl` array
+    fn is_item_frame(&self, depth: usize) -> bool {
+        depth == 3 && self.key_at(0) == Some("tl") && matches!(self.stack.get(1), Some(Frame::Array))
+    }
+
+    fn value_started(&mut self) {
+        if self.capture.is_some() || self.stack.len() < 3 || !self.is_item_frame(3) {
+            return;
+        }
+
+        let kind = match (self.stack.len(), self.key_at(2)) {
+            (3, Some("t")) => CaptureKind::Tag,
+            (4, Some("d")) if matches!(self.stack[3], Frame::Array) => CaptureKind::Data,
+            _ => return,
+        };
+
+        self.capture = Some(Capture { kind, start: self.pos, depth: self.stack.len() });
+    }
+
+    fn value_finished(&mut self, end: usize) -> Result<(), TimebaseError> {
+        let Some(capture) = self.capture.as_ref() else {
+            return Ok(());
+        };
+
+        if capture.depth != self.stack.len() {
+            return Ok(());
+        }
+
+        let bytes = &self.buf[capture.start..end];
+
+        match capture.kind {
+            CaptureKind::Tag => {
+                let tag: Tag = decode(bytes, &format!("tl[{}].t", self.item_index))?;
+                let tag = Arc::new(tag);
+                self.tag_names.push(tag.name.clone());
+                self.out.extend(self.pending.drain(..).map(|data| (tag.clone(), data)));
+                self.tag = Some(tag);
+            }
+            CaptureKind::Data => {
+                let data: TagData = decode(bytes, &format!("tl[{}].d[{}]", self.item_index, self.data_index))?;
+                self.data_index += 1;
+                match &self.tag {
+                    Some(tag) => self.out.push((tag.clone(), data)),
+                    None => self.pending.push(data),
+                }
+            }
+        }
+
+        self.capture = None;
+        Ok(())
+    }
+
+    fn item_finished(&mut self) -> Result<(), TimebaseError> {
+        if !self.pending.is_empty() {
+            return Err(decode_error(&format!("tl[{}]", self.item_index), "tag item has data but no `t` member"));
+        }
+
+        self.item_index += 1;
+        Ok(())
+    }
+}
+
+fn decode<T: DeserializeOwned>(bytes: &[u8], path: &str) -> Result<T, TimebaseError> {
+    let deserializer = &mut serde_json::Deserializer::from_slice(bytes);
+
+    serde_path_to_error::deserialize(deserializer).map_err(|e| {
+        let inner = e.path().to_string();
+        TimebaseError::Decode {
+            path: match inner.as_str() {
+                "." => path.to_string(),
+                _ => format!("{}.{}", path, inner),
+            },
+            source: e.into_inner(),
+        }
+    })
+}
+
+fn decode_error(path: &str, message: &str) -> TimebaseError {
+    TimebaseError::Decode {
+        path: path.to_string(),
+        source: <serde_json::Error as serde::de::Error>::custom(message),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timebase::testing::{listen, read_request};
+    use crate::timebase::{RetryPolicy, TimebaseClient};
+    use futures::TryStreamExt;
+    use std::io::Write;
+    use std::thread;
+    use std::time::Duration;
+
+    // Tag description and text value hold escaped quotes and brackets to exercise the scanner
+    const RESPONSE: &str = r#"{"s": "2025-01-01T00:00:00Z", "e": "2025-01-01T01:00:00Z", "tl": [
+        {"t": {"n": "FT-001", "d": "flow \"main\" {line}]"}, "d": [
+            {"t": "2025-01-01T00:00:00Z", "v": 12.5, "q": 192},
+            {"t": "2025-01-01T00:10:00Z", "v": null, "q": 0}
+        ]},
+        {"d": [{"t": "2025-01-01T00:05:00Z", "v": "open \\ [x]", "q": 192}], "t": {"n": "XV-002"}},
+        {"t": {"n": "TT-003"}, "d": []}
+    ]}"#;
+
+    type Parsed = (Vec<(Arc<Tag>, TagData)>, Vec<String>);
+
+    fn summary(points: &[(Arc<Tag>, TagData)]) -> Vec<String> {
+        points.iter()
+            .map(|(tag, data)| format!("{} {} {} {}", tag.name, data.timestamp.to_rfc3339(), serde_json::to_string(&data.value).unwrap(), data.quality))
+            .collect()
+    }
+
+    fn parse_in_chunks(response: &str, size: usize) -> Result<Parsed, TimebaseError> {
+        let mut parser = ResponseParser::default();
+        let mut points = vec![];
+
+        for chunk in response.as_bytes().chunks(size) {
+            points.extend(parser.feed(chunk)?);
+        }
+        parser.finish()?;
+
+        Ok((points, parser.tag_names))
+    }
+
+    #[test]
+    fn parses_whole_response() {
+        let (points, tag_names) = parse_in_chunks(RESPONSE, RESPONSE.len()).unwrap();
+
+        assert_eq!(tag_names, ["FT-001", "XV-002", "TT-003"]);
+        assert_eq!(summary(&points), [
+            "FT-001 2025-01-01T00:00:00+00:00 12.5 192",
+            "FT-001 2025-01-01T00:10:00+00:00 null 0",
+            "XV-002 2025-01-01T00:05:00+00:00 \"open \\\\ [x]\" 192",
+        ]);
+        assert_eq!(points[0].0.description.as_deref(), Some("flow \"main\" {line}]"));
+    }
+
+    #[test]
+    fn chunk_boundaries_do_not_change_the_result() {
+        let (expected, expected_names) = parse_in_chunks(RESPONSE, RESPONSE.len()).unwrap();
+
+        for size in 1..RESPONSE.len() {
+            let (points, tag_names) = parse_in_chunks(RESPONSE, size).unwrap();
+            assert_eq!(summary(&points), summary(&expected), "chunk size {}", size);
+            assert_eq!(tag_names, expected_names, "chunk size {}", size);
+        }
+    }
+
+    #[test]
+    fn data_before_tag_is_held_until_the_tag_arrives() {
+        let response = r#"{"tl": [{"d": [{"t": "2025-01-01T00:00:00Z", "v": 1, "q": 192}], "t": {"n": "A"}}]}"#;
+        let mut parser = ResponseParser::default();
+
+        let split = response.find(r#""t": {"#).unwrap();
+        assert!(parser.feed(&response.as_bytes()[..split]).unwrap().is_empty());
+
+        let points = parser.feed(&response.as_bytes()[split..]).unwrap();
+        assert_eq!(summary(&points), ["A 2025-01-01T00:00:00+00:00 1 192"]);
+        parser.finish().unwrap();
+    }
+
+    #[test]
+    fn data_without_tag_is_an_error() {
+        let response = r#"{"tl": [{"d": [{"t": "2025-01-01T00:00:00Z", "v": 1, "q": 192}]}]}"#;
+
+        match parse_in_chunks(response, 7) {
+            Err(TimebaseError::Decode { path, .. }) => assert_eq!(path, "tl[0]"),
+            other => panic!("expected a decode error, got {:?}", other.map(|(points, _)| summary(&points))),
+        }
+    }
+
+    #[test]
+    fn bad_point_reports_its_path() {
+        let response = r#"{"tl": [{"t": {"n": "A"}, "d": [{"t": "2025-01-01T00:00:00Z", "v": 1, "q": 192}, {"t": "later", "v": 2, "q": 192}]}]}"#;
+
+        match parse_in_chunks(response, 5) {
+            Err(TimebaseError::Decode { path, .. }) => assert_eq!(path, "tl[0].d[1].t"),
+            other => panic!("expected a decode error, got {:?}", other.map(|(points, _)| summary(&points))),
+        }
+    }
+
+    #[test]
+    fn truncated_response_is_an_error() {
+        let truncated = &RESPONSE[..RESPONSE.len() - 3];
+        assert!(matches!(parse_in_chunks(truncated, 16), Err(TimebaseError::Decode { .. })));
+    }
+
+    // Serves one request with a chunked body, sending each piece after its delay
+    fn serve_slowly(pieces: Vec<(Duration, String)>) -> String {
+        let (listener, url) = listen();
+
+        thread::spawn(move || {
+            let (mut socket, _) = listener.accept().unwrap();
+            read_request(&socket);
+
+            let head = "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nTransfer-Encoding: chunked\r\n\r\n";
+            socket.write_all(head.as_bytes()).unwrap();
+            for (delay, piece) in pieces {
+                thread::sleep(delay);
+                // The client hangs up when it gives up on a stalled body
+                if socket.write_all(format!("{:x}\r\n{}\r\n", piece.len(), piece).as_bytes()).is_err() {
+                    return;
+                }
+            }
+            let _ = socket.write_all(b"0\r\n\r\n");
+        });
+
+        url
+    }
+
+    async fn stream_slowly(pieces: Vec<(Duration, String)>, timeout: Duration) -> Result<Vec<(Arc<Tag>, TagData)>, TimebaseError> {
+        let client = TimebaseClient::from_str(&serve_slowly(pieces)).unwrap()
+            .set_timeout(timeout)
+            .set_retry_policy(RetryPolicy::none());
+        let request = client.get_data("ds").tag_name("FT-001").build().unwrap();
+
+        request.send_stream().try_collect().await
+    }
+
+    #[tokio::test]
+    async fn slow_body_may_outlast_the_timeout() {
+        let size = RESPONSE.len().div_ceil(6);
+        let pieces = RESPONSE.as_bytes()
+            .chunks(size)
+            .map(|piece| (Duration::from_millis(150), String::from_utf8(piece.to_vec()).unwrap()))
+            .collect();
+
+        let started = Instant::now();
+        let points = stream_slowly(pieces, Duration::from_millis(400)).await.unwrap();
+
+        assert!(started.elapsed() > Duration::from_millis(800));
+        assert_eq!(points.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn stalled_body_fails() {
+        let (head, tail) = RESPONSE.split_at(RESPONSE.len() / 2);
+        let pieces = vec![(Duration::ZERO, head.to_string()), (Duration::from_millis(600), tail.to_string())];
+
+        let result = stream_slowly(pieces, Duration::from_millis(200)).await;
+
+        assert!(matches!(result, Err(TimebaseError::Stalled(_))));
+    }
+}
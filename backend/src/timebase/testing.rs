@@ -0,0 +1,32 @@
+use std::io::{BufRead, BufReader, Read};
+use std::net::{TcpListener, TcpStream};
+
+// Fixtures shared by the unit tests of the timebase modules
+
+/// Binds a listener to a free local port and returns it with its base URL.
+pub(crate) fn listen() -> (TcpListener, String) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    (listener, url)
+}
+
+/// Reads one request, headers and body, so the client is not cut off while still sending.
+pub(crate) fn read_request(socket: &TcpStream) {
+    let mut reader = BufReader::new(socket);
+    let mut content_length = 0;
+
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        if line == "\r\n" || line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':')
+            && name.eq_ignore_ascii_case("content-length")
+        {
+            content_length = value.trim().parse().unwrap();
+        }
+    }
+
+    reader.read_exact(&mut vec![0; content_length]).unwrap();
+}
@@ -0,0 +1,271 @@
+use super::{check_status, Exchange, TagData, TimebaseClient, TimebaseError};
+use reqwest::StatusCode;
+use serde::Serialize;
+use std::time::Instant;
+use tracing::{info, info_span, Instrument};
+
+const DEFAULT_BATCH_SIZE: usize = 10_000;
+
+// Write requests use the same `tl`/`d` layout as data responses, with the tag reduced to
+// its name.
+#[derive(Serialize)]
+struct WriteDataBody<'a> {
+    #[serde(rename = "tl")]
+    tags: Vec<WriteTagItem<'a>>,
+}
+
+#[derive(Serialize)]
+struct WriteTagItem<'a> {
+    #[serde(rename = "n")]
+    name: &'a str,
+
+    #[serde(rename = "d")]
+    data: &'a [TagData],
+}
+
+/// Summary of a completed write.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WriteDataResult {
+    pub points: usize,
+    pub batches: usize,
+}
+
+impl TimebaseClient {
+    /// Starts a request writing data points to tags in a dataset.
+    pub fn write_data<'a>(&'a self, dataset: &'a str) -> WriteDataRequestBuilder<'a> {
+        WriteDataRequestBuilder {
+            client: self,
+            dataset_name: dataset,
+            tags: vec![],
+            batch_size: DEFAULT_BATCH_SIZE,
+        }
+    }
+}
+
+pub struct WriteDataRequestBuilder<'a> {
+    client: &'a TimebaseClient,
+    dataset_name: &'a str,
+    tags: Vec<(&'a str, Vec<TagData>)>,
+    batch_size: usize,
+}
+
+impl<'a> WriteDataRequestBuilder<'a> {
+    pub fn value(self, tag_name: &'a str, value: TagData) -> Self {
+        self.values(tag_name, [value])
+    }
+
+    pub fn values<I: IntoIterator<Item = TagData>>(mut self, tag_name: &'a str, values: I) -> Self {
+        match self.tags.iter_mut().find(|(name, _)| *name == tag_name) {
+            Some((_, data)) => data.extend(values),
+            None => self.tags.push((tag_name, values.into_iter().collect())),
+        }
+        self
+    }
+
+    /// Sets the maximum number of points posted in one request. Larger uploads are split
+    /// into several requests that are sent one after another.
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    pub async fn send(mut self) -> Result<WriteDataResult, TimebaseError> {
+        let span = info_span!(
+            "write_data",
+            dataset = %self.dataset_name,
+            tag_count = self.tags.len(),
+            points = self.tags.iter().map(|(_, data)| data.len()).sum::<usize>(),
+        );
+
+        async move {
+            let started = Instant::now();
+            let result = self.send_batches().await?;
+
+            info!(
+                batches = result.batches,
+                latency_ms = started.elapsed().as_millis() as u64,
+                "data written"
+            );
+
+            Ok(result)
+        }
+        .instrument(span)
+        .await
+    }
+
+    async fn send_batches(&mut self) -> Result<WriteDataResult, TimebaseError> {
+        let url = self.client.endpoint(&["api", "datasets", self.dataset_name, "data"])?;
+        let mut result = WriteDataResult::default();
+
+        for (_, data) in self.tags.iter_mut() {
+            data.sort_by_key(|d| d.timestamp);
+        }
+
+        for batch in self.batches() {
+            let points = batch.iter().map(|item| item.data.len()).sum::<usize>();
+            let body = WriteDataBody { tags: batch };
+            let resp = self.client.execute(self.client.http.post(url.clone()).json(&body), Exchange::Write).await?;
+
+            if resp.status() == StatusCode::NOT_FOUND {
+                return Err(TimebaseError::UnknownDataset(self.dataset_name.to_string()));
+            }
+
+            check_status(resp).await?;
+
+            result.points += points;
+            result.batches += 1;
+        }
+
+        Ok(result)
+    }
+
+    // Packs the tags into batches of at most `batch_size` points, splitting a tag across
+    // batches when it has more points than fit.
+    fn batches(&self) -> Vec<Vec<WriteTagItem<'_>>> {
+        let mut batches = vec![];
+        let mut batch = vec![];
+        let mut batch_points = 0;
+
+        for (name, data) in &self.tags {
+            let mut data = data.as_slice();
+
+            while !data.is_empty() {
+                let take = (self.batch_size - batch_points).min(data.len());
+                batch.push(WriteTagItem { name, data: &data[..take] });
+                batch_points += take;
+                data = &data[take..];
+
+                if batch_points == self.batch_size {
+                    batches.push(std::mem::take(&mut batch));
+                    batch_points = 0;
+                }
+            }
+        }
+
+        if !batch.is_empty() {
+            batches.push(batch);
+        }
+
+        batches
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timebase::testing::{listen, read_request};
+    use crate::timebase::RetryPolicy;
+    use chrono::{TimeZone, Utc};
+    use std::io::Write;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    fn points(count: i64) -> Vec<TagData> {
+        (0..count)
+            .map(|i| TagData {
+                timestamp: Utc.timestamp_opt(1_735_689_600 + i, 0).unwrap(),
+                value: None,
+                quality: 192,
+            })
+            .collect()
+    }
+
+    // Tag names and point counts of each batch
+    fn layout(builder: &WriteDataRequestBuilder) -> Vec<Vec<(String, usize)>> {
+        builder
+            .batches()
+            .iter()
+            .map(|batch| batch.iter().map(|item| (item.name.to_string(), item.data.len())).collect())
+            .collect()
+    }
+
+    #[test]
+    fn batches_fill_exactly_to_the_boundary() {
+        let client = TimebaseClient::default();
+        let builder = client.write_data("ds").values("A", points(2)).values("B", points(2)).batch_size(4);
+
+        assert_eq!(layout(&builder), vec![vec![("A".to_string(), 2), ("B".to_string(), 2)]]);
+
+        let builder = builder.values("C", points(1));
+
+        assert_eq!(
+            layout(&builder),
+            vec![vec![("A".to_string(), 2), ("B".to_string(), 2)], vec![("C".to_string(), 1)]]
+        );
+    }
+
+    #[test]
+    fn batches_split_a_tag_across_batches() {
+        let client = TimebaseClient::default();
+        let builder = client.write_data("ds").values("A", points(1)).values("B", points(7)).batch_size(3);
+
+        assert_eq!(
+            layout(&builder),
+            vec![
+                vec![("A".to_string(), 1), ("B".to_string(), 2)],
+                vec![("B".to_string(), 3)],
+                vec![("B".to_string(), 2)],
+            ]
+        );
+
+        // The pieces of a split tag keep their order
+        let batches = builder.batches();
+        let timestamps: Vec<_> = batches
+            .iter()
+            .flatten()
+            .filter(|item| item.name == "B")
+            .flat_map(|item| item.data.iter().map(|d| d.timestamp))
+            .collect();
+        assert_eq!(timestamps, points(7).iter().map(|d| d.timestamp).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn batches_of_nothing() {
+        let client = TimebaseClient::default();
+
+        assert!(client.write_data("ds").batches().is_empty());
+        assert!(client.write_data("ds").values("A", vec![]).batches().is_empty());
+    }
+
+    // Answers every request with 503 Service Unavailable and counts them
+    fn unavailable() -> (String, Arc<AtomicUsize>) {
+        let (listener, url) = listen();
+        let count = Arc::new(AtomicUsize::new(0));
+        let requests = count.clone();
+
+        thread::spawn(move || {
+            for mut socket in listener.incoming().map(Result::unwrap) {
+                read_request(&socket);
+                requests.fetch_add(1, Ordering::SeqCst);
+                let _ = socket.write_all(b"HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n");
+            }
+        });
+
+        (url, count)
+    }
+
+    #[tokio::test]
+    async fn writes_are_not_retried_by_default() {
+        let (url, count) = unavailable();
+        let client = TimebaseClient::from_str(&url).unwrap();
+
+        let result = client.write_data("ds").values("A", points(1)).send().await;
+
+        assert!(matches!(result, Err(TimebaseError::Status { .. })));
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn writes_are_retried_when_enabled() {
+        let (url, count) = unavailable();
+        let retry = RetryPolicy::default().backoff(Duration::ZERO, Duration::ZERO).retry_writes(true);
+        let client = TimebaseClient::from_str(&url).unwrap().set_retry_policy(retry);
+
+        let result = client.write_data("ds").values("A", points(1)).send().await;
+
+        assert!(matches!(result, Err(TimebaseError::Status { .. })));
+        assert_eq!(count.load(Ordering::SeqCst), 3);
+    }
+}
@@ -1,17 +1,63 @@
-use std::cmp::Ordering;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, TimeDelta, Utc};
 use std::collections::HashMap;
-use crate::timebase::TagItem;
+use std::fmt;
+use crate::timebase::{self, GetDataResponse, TagItem, TagValue};
+use serde::{Deserialize, Serialize};
 
+mod aggregate;
+mod analytics;
+mod events;
+mod export;
+mod integration;
+mod quality;
+mod resample;
+mod table;
+mod totalizer;
+#[cfg(test)]
+mod testing;
+
+pub use aggregate::{Aggregatable, Aggregate, AggregateOptions, Interpolation, QualityFilter};
+pub use analytics::{EventAnalysis, EventCalculation, EventRow, EventTable, EventValue};
+pub use events::{AttributeCapture, CaptureAt, Condition, Event, EventDefinition, EventError, EventInfo, EventSeries, Trigger};
+pub use export::{CsvOptions, ExportError};
+pub use integration::{Integrator, Reconciliation, TimeBase};
+pub use quality::{DataQuality, QualityLimit, QualityMajor, QualitySubstatus};
+pub use resample::{time_grid, ResampleMethod};
+pub use table::{align, AlignOptions, Column, Fill, Row, WideTable};
+pub use totalizer::Totalizer;
+
+// Serialized as a plain number or string, like the Timebase wire format
 #[derive(Debug)]
-#[derive(Clone)]
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
 pub enum DataValue {
     Integer(i32),
     Float(f64),
     Text(String)
 }
 
-#[derive(Debug)]
+impl fmt::Display for DataValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DataValue::Integer(v) => write!(f, "{}", v),
+            DataValue::Float(v) => write!(f, "{}", v),
+            DataValue::Text(v) => f.write_str(v),
+        }
+    }
+}
+
+impl DataValue {
+    /// Returns the value as a number, parsing text values where possible.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            DataValue::Integer(v) => Some(*v as f64),
+            DataValue::Float(v) => Some(*v),
+            DataValue::Text(v) => v.trim().parse().ok(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub struct Tag {
     pub name: String,
     pub description: Option<String>,
@@ -21,21 +67,27 @@ pub struct Tag {
     pub fields: HashMap<String, String>,
 }
 
-#[derive(Debug)]
-pub enum DataQuality {
-    Good(i16),
-    Bad(i16),
-    Unknown(i16)
+impl Tag {
+    /// Formats a value of this tag, replacing integer states with their state text.
+    pub fn format_value(&self, value: &DataValue) -> String {
+        match value {
+            DataValue::Integer(v) => match self.states.get(v) {
+                Some(text) => text.clone(),
+                None => v.to_string(),
+            },
+            _ => value.to_string(),
+        }
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize)]
 pub struct DataPoint {
     pub timestamp: DateTime<Utc>,
     pub value: Option<DataValue>,
     pub quality: DataQuality
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct DataSeries {
     pub tag: Tag,
     pub data: Vec<DataPoint>
@@ -47,56 +99,174 @@ pub struct DataPoint2<T> {
     pub value: Option<T>,
 }
 
+/// What a lookup returns for times outside the range covered by a series.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BoundaryPolicy {
+    /// There is no value outside the data range.
+    #[default]
+    None,
+    /// The first point stands for earlier times and the last point for later times.
+    Nearest,
+}
+
+/// The points of a series that fall in `[start, end)`, borrowed from the series.
 #[derive(Debug)]
-struct TimeSlice<'a, T> {
-    start: DateTime<Utc>,
-    end: DateTime<Utc>,
-    data: &'a [DataPoint2<T>]
+pub struct TimeSlice<'a> {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    /// Value in effect at `start`, synthesized from the surrounding points when requested and
+    /// no point lies exactly on `start`. Only time-based aggregates and time in state use it.
+    pub boundary: Option<DataPoint>,
+    pub data: DataPointSlice<'a>
+}
+
+impl TimeSlice<'_> {
+    /// The boundary point, if any, followed by the points in the slice.
+    pub fn points(&self) -> impl Iterator<Item = &DataPoint> + Clone {
+        self.boundary.iter().chain(self.data.iter())
+    }
+
+    // Time-weighted aggregates cover the slice unless the caller chose another window
+    fn options(&self, options: &AggregateOptions) -> AggregateOptions {
+        AggregateOptions {
+            window: options.window.or(Some((self.start, self.end))),
+            ..*options
+        }
+    }
 }
 
 
 impl DataSeries {
+    /// The value in effect at `timestamp`, i.e. the value of the last point at or before it.
+    /// Returns `None` before the first point.
     pub fn get_value_at(&self, timestamp: DateTime<Utc>) -> Option<&DataValue> {
-        if self.data.is_empty() {
-            return None;
+        self.value_at_or_before(timestamp, BoundaryPolicy::None)?.value.as_ref()
+    }
+
+    /// The last point at or before `timestamp`. Before the first point this is `None`, or
+    /// the first point with [`BoundaryPolicy::Nearest`].
+    pub fn value_at_or_before(&self, timestamp: DateTime<Utc>, policy: BoundaryPolicy) -> Option<&DataPoint> {
+        match self.data.partition_point(|dp| dp.timestamp <= timestamp) {
+            0 if policy == BoundaryPolicy::Nearest => self.data.first(),
+            0 => None,
+            after => Some(&self.data[after - 1]),
         }
+    }
 
-        let mut min = 0;
-        let mut max = self.data.len() - 1;
-        let mut index = (min + max) / 2;
-
-        while max - min > 1 {
-            match self.data[index].timestamp.cmp(&timestamp) {
-                Ordering::Less => min = index,
-                Ordering::Greater => max = index,
-                Ordering::Equal => {
-                    min = index;
-                    max = index;
-                }
-            }
-            index = (min + max) / 2;
+    /// The first point at or after `timestamp`. After the last point this is `None`, or the
+    /// last point with [`BoundaryPolicy::Nearest`].
+    pub fn value_at_or_after(&self, timestamp: DateTime<Utc>, policy: BoundaryPolicy) -> Option<&DataPoint> {
+        match self.data.get(self.data.partition_point(|dp| dp.timestamp < timestamp)) {
+            None if policy == BoundaryPolicy::Nearest => self.data.last(),
+            point => point,
+        }
+    }
+
+    /// A point at `timestamp` with the value interpolated linearly between the surrounding
+    /// points and the quality of the earlier one. Non-numeric values hold until the next
+    /// point. Outside the data range this is `None`, or the nearest point's value with
+    /// [`BoundaryPolicy::Nearest`].
+    pub fn interpolated_value_at(&self, timestamp: DateTime<Utc>, policy: BoundaryPolicy) -> Option<DataPoint> {
+        let after = self.data.partition_point(|dp| dp.timestamp <= timestamp);
+
+        let (nearest, value) = match (after.checked_sub(1).map(|i| &self.data[i]), self.data.get(after)) {
+            (Some(before), _) if before.timestamp == timestamp => (before, before.value.clone()),
+            (Some(before), Some(next)) => (before, interpolate(before, next, timestamp).or_else(|| before.value.clone())),
+            (Some(last), None) if policy == BoundaryPolicy::Nearest => (last, last.value.clone()),
+            (None, Some(first)) if policy == BoundaryPolicy::Nearest => (first, first.value.clone()),
+            _ => return None,
+        };
+
+        Some(DataPoint { timestamp, value, quality: nearest.quality })
+    }
+
+    /// Linear for analog tags, step for tags with state texts or non-float values.
+    pub fn default_interpolation(&self) -> Interpolation {
+        if !self.tag.states.is_empty() {
+            return Interpolation::Step;
         }
 
-        self.data[min].value.as_ref()
+        match self.data.iter().find_map(|dp| dp.value.as_ref()) {
+            Some(DataValue::Float(_)) => Interpolation::Linear,
+            _ => Interpolation::Step,
+        }
     }
 
-    fn slice(&self, sections: Vec<DateTime<Utc>>) -> Vec<DataPointSlice<'_>> {
-        vec![&self.data[0..1]]
+    /// Splits the series at the given ascending boundaries into `boundaries.len() - 1`
+    /// contiguous slices `[b0, b1)`, `[b1, b2)`, ... without copying points.
+    ///
+    /// With `boundary_values` set, each slice that has no point exactly at its start gets a
+    /// synthesized point holding the value in effect there, interpolated from its neighbours.
+    pub fn slice(&self, boundaries: &[DateTime<Utc>], boundary_values: Option<Interpolation>) -> Vec<TimeSlice<'_>> {
+        boundaries.windows(2).map(|bounds| {
+            let (start, end) = (bounds[0], bounds[1]);
+            let first = self.data.partition_point(|dp| dp.timestamp < start);
+            let last = first + self.data[first..].partition_point(|dp| dp.timestamp < end);
+
+            let boundary = match boundary_values {
+                Some(_) if self.data.get(first).is_some_and(|dp| dp.timestamp == start) => None,
+                Some(interpolation) => self.boundary_point(first, start, interpolation),
+                None => None,
+            };
+
+            TimeSlice { start, end, boundary, data: &self.data[first..last] }
+        }).collect()
     }
-}
 
-trait Aggregatable {
-    fn aggregate<T>(&self) -> T;
+    // Builds the point in effect at `timestamp`, where `index` is the first point after it
+    fn boundary_point(&self, index: usize, timestamp: DateTime<Utc>, interpolation: Interpolation) -> Option<DataPoint> {
+        let before = self.data.get(index.checked_sub(1)?)?;
+
+        let value = match (interpolation, self.data.get(index)) {
+            (Interpolation::Linear, Some(after)) => interpolate(before, after, timestamp)
+                .or_else(|| before.value.clone()),
+            _ => before.value.clone(),
+        };
+
+        Some(DataPoint { timestamp, value, quality: before.quality })
+    }
 }
 
-type DataPointSlice<'a> = &'a [DataPoint];
+pub type DataPointSlice<'a> = &'a [DataPoint];
 
 impl Aggregatable for DataPointSlice<'_> {
-    fn aggregate<T>(&self) -> T {
-        todo!()
+    fn aggregate(&self, aggregate: Aggregate, options: &AggregateOptions) -> Option<f64> {
+        aggregate::aggregate_points(self.iter(), aggregate, options)
+    }
+
+    fn time_in_state(&self, options: &AggregateOptions) -> Vec<(DataValue, TimeDelta)> {
+        aggregate::time_in_state(self.iter(), options)
+    }
+}
+
+impl Aggregatable for TimeSlice<'_> {
+    fn aggregate(&self, aggregate: Aggregate, options: &AggregateOptions) -> Option<f64> {
+        // The boundary point is not a recorded sample, so it must not count as one
+        match aggregate.follows_time() {
+            true => aggregate::aggregate_points(self.points(), aggregate, &self.options(options)),
+            false => aggregate::aggregate_points(self.data.iter(), aggregate, options),
+        }
+    }
+
+    fn time_in_state(&self, options: &AggregateOptions) -> Vec<(DataValue, TimeDelta)> {
+        aggregate::time_in_state(self.points(), &self.options(options))
     }
 }
 
+// Linear interpolation between two numeric points. Non-numeric values cannot be interpolated.
+fn interpolate(before: &DataPoint, after: &DataPoint, timestamp: DateTime<Utc>) -> Option<DataValue> {
+    let from = before.value.as_ref()?.as_f64()?;
+    let to = after.value.as_ref()?.as_f64()?;
+    let span = (after.timestamp - before.timestamp).as_seconds_f64();
+
+    if span <= 0.0 {
+        return Some(DataValue::Float(from));
+    }
+
+    let fraction = (timestamp - before.timestamp).as_seconds_f64() / span;
+    Some(DataValue::Float(from + (to - from) * fraction))
+}
+
 impl From<&TagItem> for Vec<DataPoint2<i32>> {
     fn from(item: &TagItem) -> Self {
         item.data.iter().map(|d| {
@@ -106,10 +276,7 @@ impl From<&TagItem> for Vec<DataPoint2<i32>> {
                     None => None,
                     Some(crate::timebase::TagValue::Integer(v)) => Some(*v),
                     Some(crate::timebase::TagValue::Float(v)) => Some(v.round() as i32),
-                    Some(crate::timebase::TagValue::Text(v)) => match v.parse::<i32>() {
-                        Ok(v) => Some(v),
-                        Err(_) => None
-                    },
+                    Some(crate::timebase::TagValue::Text(v)) => v.parse::<i32>().ok(),
                 },
             }
         }).collect()
@@ -132,4 +299,214 @@ impl TagItem {
             }
         }).collect()
     }
-}
\ No newline at end of file
+}
+
+impl From<&timebase::Tag> for Tag {
+    fn from(tag: &timebase::Tag) -> Self {
+        Tag {
+            name: tag.name.clone(),
+            description: tag.description.clone(),
+            format: tag.format.clone(),
+            uom: match &tag.uom {
+                None => Default::default(),
+                Some(uom) => match uom.len() {
+                    1 => Some(uom.values().next().unwrap().clone()),
+                    _ => Default::default()
+                }
+            },
+            states: match &tag.uom {
+                None => Default::default(),
+                Some(uom) => match uom.len() {
+                    n if n > 1 => {
+                        uom.iter().map(|(k, v)| (*k, v.clone())).collect()
+                    },
+                    _ => Default::default()
+                }
+            },
+            fields: tag.fields.clone().unwrap_or_default(),
+        }
+    }
+}
+
+impl GetDataResponse {
+    #[tracing::instrument(level = "debug", skip_all, fields(tag_count = self.tags.len(), points))]
+    pub fn time_series(&self) -> Vec<DataSeries> {
+        let series: Vec<DataSeries> = self.tags.iter().map(|tl| {
+            // 4. Return the data points in our own data model
+            DataSeries {
+                tag: Tag::from(&tl.tag),
+                data: tl.data.iter().map(|dp| {
+                    DataPoint {
+                        timestamp: dp.timestamp,
+                        value: match &dp.value {
+                            Some(TagValue::Integer(v)) => Some(DataValue::Integer(*v)),
+                            Some(TagValue::Float(v)) => Some(DataValue::Float(*v)),
+                            Some(TagValue::Text(v)) => Some(DataValue::Text(v.clone())),
+                            None => None,
+                        },
+                        quality: DataQuality::from(dp.quality),
+                    }
+                }).collect()
+            }
+        }).collect();
+
+        tracing::Span::current().record("points", series.iter().map(|s| s.data.len()).sum::<usize>());
+
+        series
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timeseries::testing::{at, floats, point, tag};
+
+    fn close(actual: Option<f64>, expected: f64) -> bool {
+        actual.is_some_and(|actual| (actual - expected).abs() < 1e-9)
+    }
+
+    #[test]
+    fn sample_aggregates_ignore_the_boundary_point() {
+        let series = floats("FT", &[(0, 10.0), (10, 40.0), (20, 30.0)]);
+        let options = AggregateOptions::default();
+
+        for boundary_values in [None, Some(Interpolation::Step), Some(Interpolation::Linear)] {
+            let slices = series.slice(&[at(5), at(25)], boundary_values);
+            let slice = &slices[0];
+            assert_eq!(slice.boundary.is_some(), boundary_values.is_some());
+
+            assert_eq!(slice.min(&options), Some(30.0));
+            assert_eq!(slice.max(&options), Some(40.0));
+            assert_eq!(slice.first(&options), Some(40.0));
+            assert_eq!(slice.last(&options), Some(30.0));
+            assert_eq!(slice.count(&options), 2);
+            assert_eq!(slice.average(&options), Some(35.0));
+            assert_eq!(slice.range(&options), Some(10.0));
+            assert!(close(slice.std_dev(&options), 50f64.sqrt()));
+        }
+    }
+
+    #[test]
+    fn time_based_aggregates_start_from_the_boundary_point() {
+        let series = floats("FT", &[(0, 10.0), (10, 40.0), (20, 30.0)]);
+        let options = AggregateOptions::default();
+
+        // 10 holds from 5 to 10, then 40 for 10s and 30 for 5s
+        let with = &series.slice(&[at(5), at(25)], Some(Interpolation::Step))[0];
+        assert_eq!(with.delta(&options), Some(20.0));
+        assert_eq!(with.time_weighted_average(&options), Some(30.0));
+        assert_eq!(with.time_in_state(&options), [
+            (DataValue::Float(10.0), TimeDelta::seconds(5)),
+            (DataValue::Float(40.0), TimeDelta::seconds(10)),
+            (DataValue::Float(30.0), TimeDelta::seconds(5)),
+        ]);
+
+        // Without it the slice has no value before its first point
+        let without = &series.slice(&[at(5), at(25)], None)[0];
+        assert_eq!(without.delta(&options), Some(-10.0));
+        assert!(close(without.time_weighted_average(&options), 550.0 / 15.0));
+        assert_eq!(without.time_in_state(&options), [
+            (DataValue::Float(40.0), TimeDelta::seconds(10)),
+            (DataValue::Float(30.0), TimeDelta::seconds(5)),
+        ]);
+    }
+
+    #[test]
+    fn empty_slice_with_a_boundary_has_no_samples() {
+        let series = floats("FT", &[(0, 10.0), (20, 30.0)]);
+        let options = AggregateOptions::new(Interpolation::Linear);
+        let slice = &series.slice(&[at(5), at(15)], Some(Interpolation::Linear))[0];
+
+        assert!(slice.data.is_empty());
+        assert_eq!(slice.count(&options), 0);
+        assert_eq!(slice.average(&options), None);
+        assert_eq!(slice.max(&options), None);
+
+        // Only the boundary, interpolated to 15 at 5, is in effect in the slice
+        assert_eq!(slice.boundary.as_ref().and_then(|b| b.value.clone()), Some(DataValue::Float(15.0)));
+        assert_eq!(slice.time_weighted_average(&options), Some(15.0));
+        assert_eq!(slice.delta(&options), Some(0.0));
+    }
+
+    fn seconds(point: Option<&DataPoint>) -> Option<i64> {
+        point.map(|p| (p.timestamp - at(0)).num_seconds())
+    }
+
+    #[test]
+    fn value_at_or_before() {
+        let series = floats("FT", &[(10, 1.0), (20, 3.0)]);
+        let before = |t, policy| seconds(series.value_at_or_before(at(t), policy));
+
+        assert_eq!(before(5, BoundaryPolicy::None), None);
+        assert_eq!(before(5, BoundaryPolicy::Nearest), Some(10));
+        for policy in [BoundaryPolicy::None, BoundaryPolicy::Nearest] {
+            assert_eq!(before(10, policy), Some(10));
+            assert_eq!(before(15, policy), Some(10));
+            assert_eq!(before(20, policy), Some(20));
+            assert_eq!(before(25, policy), Some(20));
+        }
+
+        assert_eq!(series.get_value_at(at(5)), None);
+        assert_eq!(series.get_value_at(at(15)), Some(&DataValue::Float(1.0)));
+    }
+
+    #[test]
+    fn value_at_or_after() {
+        let series = floats("FT", &[(10, 1.0), (20, 3.0)]);
+        let after = |t, policy| seconds(series.value_at_or_after(at(t), policy));
+
+        for policy in [BoundaryPolicy::None, BoundaryPolicy::Nearest] {
+            assert_eq!(after(5, policy), Some(10));
+            assert_eq!(after(10, policy), Some(10));
+            assert_eq!(after(15, policy), Some(20));
+            assert_eq!(after(20, policy), Some(20));
+        }
+        assert_eq!(after(25, BoundaryPolicy::None), None);
+        assert_eq!(after(25, BoundaryPolicy::Nearest), Some(20));
+    }
+
+    #[test]
+    fn interpolated_value_at() {
+        let mut series = floats("FT", &[(10, 1.0), (20, 3.0)]);
+        series.data[0].quality = DataQuality::UNCERTAIN;
+        let value = |t, policy| {
+            let point = series.interpolated_value_at(at(t), policy)?;
+            assert_eq!(point.timestamp, at(t));
+            Some((point.value?.as_f64()?, point.quality))
+        };
+
+        assert_eq!(value(5, BoundaryPolicy::None), None);
+        assert_eq!(value(5, BoundaryPolicy::Nearest), Some((1.0, DataQuality::UNCERTAIN)));
+        for policy in [BoundaryPolicy::None, BoundaryPolicy::Nearest] {
+            assert_eq!(value(10, policy), Some((1.0, DataQuality::UNCERTAIN)));
+            // The quality is the earlier point's
+            assert_eq!(value(15, policy), Some((2.0, DataQuality::UNCERTAIN)));
+            assert_eq!(value(20, policy), Some((3.0, DataQuality::GOOD)));
+        }
+        assert_eq!(value(25, BoundaryPolicy::None), None);
+        assert_eq!(value(25, BoundaryPolicy::Nearest), Some((3.0, DataQuality::GOOD)));
+    }
+
+    #[test]
+    fn interpolated_text_holds_until_the_next_point() {
+        let series = DataSeries {
+            tag: tag("MODE"),
+            data: vec![point(10, DataValue::Text("auto".into())), point(20, DataValue::Text("manual".into()))],
+        };
+        let value = |t| series.interpolated_value_at(at(t), BoundaryPolicy::None).and_then(|p| p.value);
+
+        assert_eq!(value(15), Some(DataValue::Text("auto".into())));
+        assert_eq!(value(20), Some(DataValue::Text("manual".into())));
+    }
+
+    #[test]
+    fn lookups_in_an_empty_series() {
+        let series = floats("FT", &[]);
+
+        for policy in [BoundaryPolicy::None, BoundaryPolicy::Nearest] {
+            assert!(series.value_at_or_before(at(0), policy).is_none());
+            assert!(series.value_at_or_after(at(0), policy).is_none());
+            assert!(series.interpolated_value_at(at(0), policy).is_none());
+        }
+    }
+}
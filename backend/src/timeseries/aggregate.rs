@@ -0,0 +1,408 @@
+use super::{DataPoint, DataQuality, DataValue};
+use chrono::{DateTime, TimeDelta, Utc};
+
+/// How the value of a tag is assumed to change between two recorded points.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interpolation {
+    /// The value holds until the next point, as for discrete and state tags.
+    Step,
+    /// The value changes linearly towards the next point, as for analog tags.
+    Linear,
+}
+
+/// Which points an aggregate takes into account.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QualityFilter {
+    /// Only good points.
+    Good,
+    /// Good and uncertain points.
+    Usable,
+    /// Every point, regardless of quality.
+    All,
+}
+
+impl QualityFilter {
+    pub fn accepts(&self, quality: DataQuality) -> bool {
+        match self {
+            QualityFilter::Good => quality.is_good(),
+            QualityFilter::Usable => quality.is_usable(),
+            QualityFilter::All => true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aggregate {
+    Min,
+    Max,
+    First,
+    Last,
+    Count,
+    /// Arithmetic mean of the recorded values.
+    Average,
+    /// Mean of the value over time, following the interpolation.
+    TimeWeightedAverage,
+    /// Max minus min.
+    Range,
+    /// Sample standard deviation of the recorded values.
+    StdDev,
+    /// Last minus first.
+    Delta,
+}
+
+impl Aggregate {
+    /// True for aggregates that follow the value over time, so the value in effect at the
+    /// start of a window counts towards them. The others summarize the recorded samples.
+    pub fn follows_time(&self) -> bool {
+        matches!(self, Aggregate::TimeWeightedAverage | Aggregate::Delta)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct AggregateOptions {
+    pub interpolation: Interpolation,
+    pub quality: QualityFilter,
+    /// Time range covered by time-weighted aggregates. Defaults to the first to the last
+    /// point. A point before the start supplies the value in effect at the start.
+    pub window: Option<(DateTime<Utc>, DateTime<Utc>)>,
+}
+
+impl Default for AggregateOptions {
+    fn default() -> Self {
+        Self {
+            interpolation: Interpolation::Step,
+            quality: QualityFilter::Usable,
+            window: None,
+        }
+    }
+}
+
+impl AggregateOptions {
+    pub fn new(interpolation: Interpolation) -> Self {
+        Self { interpolation, ..Default::default() }
+    }
+
+    pub fn quality(mut self, quality: QualityFilter) -> Self {
+        self.quality = quality;
+        self
+    }
+
+    pub fn window(mut self, start: DateTime<Utc>, end: DateTime<Utc>) -> Self {
+        self.window = Some((start, end));
+        self
+    }
+}
+
+/// Standard historian aggregates over a run of data points. Points whose quality the
+/// filter rejects are skipped; in time-weighted aggregates the time they cover is left out.
+pub trait Aggregatable {
+    fn aggregate(&self, aggregate: Aggregate, options: &AggregateOptions) -> Option<f64>;
+
+    /// Total time spent in each distinct value, in order of first appearance. Values always
+    /// hold until the next point, whatever the interpolation.
+    fn time_in_state(&self, options: &AggregateOptions) -> Vec<(DataValue, TimeDelta)>;
+
+    fn min(&self, options: &AggregateOptions) -> Option<f64> {
+        self.aggregate(Aggregate::Min, options)
+    }
+
+    fn max(&self, options: &AggregateOptions) -> Option<f64> {
+        self.aggregate(Aggregate::Max, options)
+    }
+
+    fn first(&self, options: &AggregateOptions) -> Option<f64> {
+        self.aggregate(Aggregate::First, options)
+    }
+
+    fn last(&self, options: &AggregateOptions) -> Option<f64> {
+        self.aggregate(Aggregate::Last, options)
+    }
+
+    fn count(&self, options: &AggregateOptions) -> usize {
+        self.aggregate(Aggregate::Count, options).unwrap_or_default() as usize
+    }
+
+    fn average(&self, options: &AggregateOptions) -> Option<f64> {
+        self.aggregate(Aggregate::Average, options)
+    }
+
+    fn time_weighted_average(&self, options: &AggregateOptions) -> Option<f64> {
+        self.aggregate(Aggregate::TimeWeightedAverage, options)
+    }
+
+    fn range(&self, options: &AggregateOptions) -> Option<f64> {
+        self.aggregate(Aggregate::Range, options)
+    }
+
+    fn std_dev(&self, options: &AggregateOptions) -> Option<f64> {
+        self.aggregate(Aggregate::StdDev, options)
+    }
+
+    fn delta(&self, options: &AggregateOptions) -> Option<f64> {
+        self.aggregate(Aggregate::Delta, options)
+    }
+}
+
+pub(crate) fn aggregate_points<'a, I>(points: I, aggregate: Aggregate, options: &AggregateOptions) -> Option<f64>
+where
+    I: Iterator<Item = &'a DataPoint> + Clone,
+{
+    let accepted = points.clone().filter(|p| options.quality.accepts(p.quality));
+    let mut values = accepted.clone().filter_map(|p| p.value.as_ref().and_then(DataValue::as_f64));
+
+    match aggregate {
+        Aggregate::Count => Some(accepted.filter(|p| p.value.is_some()).count() as f64),
+        Aggregate::Min => values.reduce(f64::min),
+        Aggregate::Max => values.reduce(f64::max),
+        Aggregate::First => values.next(),
+        Aggregate::Last => values.last(),
+        Aggregate::Range => {
+            let (min, max) = values.fold(None, |acc: Option<(f64, f64)>, v| match acc {
+                None => Some((v, v)),
+                Some((min, max)) => Some((min.min(v), max.max(v))),
+            })?;
+            Some(max - min)
+        }
+        Aggregate::Delta => {
+            let first = values.next()?;
+            Some(values.last().unwrap_or(first) - first)
+        }
+        Aggregate::Average => {
+            let (sum, n) = values.fold((0.0, 0), |(sum, n), v| (sum + v, n + 1));
+            (n > 0).then(|| sum / n as f64)
+        }
+        Aggregate::StdDev => {
+            let values: Vec<f64> = values.collect();
+            if values.len() < 2 {
+                return None;
+            }
+            let mean = values.iter().sum::<f64>() / values.len() as f64;
+            let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (values.len() - 1) as f64;
+            Some(variance.sqrt())
+        }
+        Aggregate::TimeWeightedAverage => time_weighted_average(points, options),
+    }
+}
+
+// Integrates the value over the window and divides by the time that had an accepted value
+fn time_weighted_average<'a, I>(points: I, options: &AggregateOptions) -> Option<f64>
+where
+    I: Iterator<Item = &'a DataPoint> + Clone,
+{
+    let (area, seconds) = area(points, options)?;
+
+    (seconds > 0.0).then(|| area / seconds)
+}
+
+/// The integral of the value over the window in value-seconds, and the number of seconds
+/// that had an accepted value.
+pub(crate) fn area<'a, I>(points: I, options: &AggregateOptions) -> Option<(f64, f64)>
+where
+    I: Iterator<Item = &'a DataPoint> + Clone,
+{
+    let (start, end) = window(points.clone(), options)?;
+    let mut area = 0.0;
+    let mut seconds = 0.0;
+
+    for (point, next) in segments(points) {
+        let segment_start = point.timestamp.max(start);
+        let segment_end = next.map_or(end, |n| n.timestamp.min(end));
+        if segment_end <= segment_start || !options.quality.accepts(point.quality) {
+            continue;
+        }
+
+        let Some(value) = point.value.as_ref().and_then(DataValue::as_f64) else {
+            continue;
+        };
+
+        let duration = (segment_end - segment_start).as_seconds_f64();
+
+        // Linear segments need a usable value at the far end, otherwise the value holds
+        let next_value = next
+            .filter(|n| options.interpolation == Interpolation::Linear && options.quality.accepts(n.quality))
+            .and_then(|n| n.value.as_ref().and_then(DataValue::as_f64).map(|v| (n.timestamp, v)));
+
+        area += match next_value {
+            Some((next_time, next_value)) => {
+                let at = |t: DateTime<Utc>| {
+                    let span = (next_time - point.timestamp).as_seconds_f64();
+                    value + (next_value - value) * (t - point.timestamp).as_seconds_f64() / span
+                };
+                (at(segment_start) + at(segment_end)) / 2.0 * duration
+            }
+            None => value * duration,
+        };
+        seconds += duration;
+    }
+
+    Some((area, seconds))
+}
+
+pub(crate) fn time_in_state<'a, I>(points: I, options: &AggregateOptions) -> Vec<(DataValue, TimeDelta)>
+where
+    I: Iterator<Item = &'a DataPoint> + Clone,
+{
+    let mut states: Vec<(DataValue, TimeDelta)> = vec![];
+    let Some((start, end)) = window(points.clone(), options) else {
+        return states;
+    };
+
+    for (point, next) in segments(points) {
+        let segment_start = point.timestamp.max(start);
+        let segment_end = next.map_or(end, |n| n.timestamp.min(end));
+        if segment_end <= segment_start || !options.quality.accepts(point.quality) {
+            continue;
+        }
+
+        let Some(value) = &point.value else {
+            continue;
+        };
+
+        match states.iter_mut().find(|(state, _)| state == value) {
+            Some((_, duration)) => *duration += segment_end - segment_start,
+            None => states.push((value.clone(), segment_end - segment_start)),
+        }
+    }
+
+    states
+}
+
+fn window<'a, I>(mut points: I, options: &AggregateOptions) -> Option<(DateTime<Utc>, DateTime<Utc>)>
+where
+    I: Iterator<Item = &'a DataPoint> + Clone,
+{
+    match options.window {
+        Some(window) => Some(window),
+        None => {
+            let first = points.next()?.timestamp;
+            Some((first, points.last().map_or(first, |p| p.timestamp)))
+        }
+    }
+}
+
+// Pairs every point with the one after it
+fn segments<'a, I>(points: I) -> impl Iterator<Item = (&'a DataPoint, Option<&'a DataPoint>)>
+where
+    I: Iterator<Item = &'a DataPoint> + Clone,
+{
+    let mut next = points.clone();
+    next.next();
+    points.map(move |point| (point, next.next()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timeseries::testing::at;
+
+    fn points(values: &[(i64, Option<DataValue>, DataQuality)]) -> Vec<DataPoint> {
+        values.iter()
+            .map(|(seconds, value, quality)| DataPoint { timestamp: at(*seconds), value: value.clone(), quality: *quality })
+            .collect()
+    }
+
+    fn float(v: f64) -> Option<DataValue> {
+        Some(DataValue::Float(v))
+    }
+
+    // The bad point in the middle is skipped by the default quality filter
+    fn sample() -> Vec<DataPoint> {
+        points(&[
+            (0, float(10.0), DataQuality::GOOD),
+            (10, float(40.0), DataQuality::BAD),
+            (20, float(30.0), DataQuality::GOOD),
+            (30, float(20.0), DataQuality::UNCERTAIN),
+        ])
+    }
+
+    #[test]
+    fn standard_aggregates_skip_rejected_points() {
+        let data = sample();
+        let options = AggregateOptions::default();
+        let aggregate = |aggregate| aggregate_points(data.iter(), aggregate, &options);
+
+        assert_eq!(aggregate(Aggregate::Min), Some(10.0));
+        assert_eq!(aggregate(Aggregate::Max), Some(30.0));
+        assert_eq!(aggregate(Aggregate::First), Some(10.0));
+        assert_eq!(aggregate(Aggregate::Last), Some(20.0));
+        assert_eq!(aggregate(Aggregate::Count), Some(3.0));
+        assert_eq!(aggregate(Aggregate::Average), Some(20.0));
+        assert_eq!(aggregate(Aggregate::Range), Some(20.0));
+        assert_eq!(aggregate(Aggregate::Delta), Some(10.0));
+        assert_eq!(aggregate(Aggregate::StdDev), Some(10.0));
+    }
+
+    #[test]
+    fn quality_filter_selects_points() {
+        let data = sample();
+
+        let all = AggregateOptions::default().quality(QualityFilter::All);
+        assert_eq!(aggregate_points(data.iter(), Aggregate::Max, &all), Some(40.0));
+        assert_eq!(aggregate_points(data.iter(), Aggregate::Count, &all), Some(4.0));
+
+        let good = AggregateOptions::default().quality(QualityFilter::Good);
+        assert_eq!(aggregate_points(data.iter(), Aggregate::Last, &good), Some(30.0));
+    }
+
+    #[test]
+    fn empty_input() {
+        let data: Vec<DataPoint> = vec![];
+        let options = AggregateOptions::default();
+
+        assert_eq!(aggregate_points(data.iter(), Aggregate::Count, &options), Some(0.0));
+        assert_eq!(aggregate_points(data.iter(), Aggregate::Average, &options), None);
+        assert_eq!(aggregate_points(data.iter(), Aggregate::TimeWeightedAverage, &options), None);
+        assert_eq!(area(data.iter(), &options), None);
+
+        // A standard deviation needs two values
+        let single = points(&[(0, float(1.0), DataQuality::GOOD)]);
+        assert_eq!(aggregate_points(single.iter(), Aggregate::StdDev, &options), None);
+    }
+
+    #[test]
+    fn step_area_leaves_out_rejected_time() {
+        let data = sample();
+
+        // 10 for 10s, the bad point's 10s left out, then 30 for 10s
+        assert_eq!(area(data.iter(), &AggregateOptions::new(Interpolation::Step)), Some((400.0, 20.0)));
+        assert_eq!(aggregate_points(data.iter(), Aggregate::TimeWeightedAverage, &AggregateOptions::default()), Some(20.0));
+    }
+
+    #[test]
+    fn linear_area_holds_before_a_rejected_point() {
+        let data = sample();
+
+        // 10 holds up to the bad point, then 30 falls to 20 over 10s
+        assert_eq!(area(data.iter(), &AggregateOptions::new(Interpolation::Linear)), Some((350.0, 20.0)));
+    }
+
+    #[test]
+    fn window_clips_and_extends_segments() {
+        let data = points(&[(0, float(0.0), DataQuality::GOOD), (10, float(10.0), DataQuality::GOOD)]);
+
+        // The point before the window supplies the start value; the last value holds to the end
+        let options = AggregateOptions::new(Interpolation::Linear).window(at(5), at(25));
+        assert_eq!(area(data.iter(), &options), Some((187.5, 20.0)));
+
+        let options = AggregateOptions::new(Interpolation::Step).window(at(5), at(25));
+        assert_eq!(area(data.iter(), &options), Some((150.0, 20.0)));
+    }
+
+    #[test]
+    fn time_in_state_totals_each_value() {
+        let on = Some(DataValue::Integer(1));
+        let off = Some(DataValue::Integer(0));
+        let data = points(&[
+            (0, on.clone(), DataQuality::GOOD),
+            (10, off.clone(), DataQuality::GOOD),
+            (15, on.clone(), DataQuality::GOOD),
+            (30, off.clone(), DataQuality::GOOD),
+        ]);
+
+        let states = time_in_state(data.iter(), &AggregateOptions::new(Interpolation::Linear));
+        assert_eq!(states, [
+            (DataValue::Integer(1), TimeDelta::seconds(25)),
+            (DataValue::Integer(0), TimeDelta::seconds(5)),
+        ]);
+    }
+}
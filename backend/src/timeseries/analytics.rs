@@ -0,0 +1,313 @@
+use super::{Aggregatable, Aggregate, AggregateOptions, DataSeries, DataValue, EventError, EventSeries, Interpolation, Totalizer};
+use chrono::{DateTime, TimeDelta, Utc};
+
+/// A value computed for one event.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EventValue {
+    Number(f64),
+    Text(String),
+}
+
+#[derive(Debug, Clone)]
+pub enum EventCalculation {
+    /// Event duration in seconds.
+    Duration,
+    /// How much a counter tag increased over the event, allowing for resets and rollovers.
+    Totalized { tag: String, totalizer: Totalizer },
+    /// Time-weighted average of a tag over the event.
+    Average { tag: String },
+    /// Any standard aggregate of a tag over the event.
+    Aggregate { tag: String, aggregate: Aggregate },
+    /// Seconds spent in each state of a tag, as one column per state seen in any event.
+    TimeInStates { tag: String },
+    /// An attribute captured when the events were detected.
+    Attribute { name: String },
+}
+
+#[derive(Debug, Clone)]
+struct EventColumn {
+    name: String,
+    calculation: EventCalculation,
+}
+
+#[derive(Debug, Clone)]
+pub struct EventRow {
+    pub start_time: DateTime<Utc>,
+    pub end_time: Option<DateTime<Utc>>,
+    /// One value per column, `None` where there was no data.
+    pub values: Vec<Option<EventValue>>,
+}
+
+/// Per-event results with one row per event.
+#[derive(Debug, Clone)]
+pub struct EventTable {
+    pub columns: Vec<String>,
+    pub rows: Vec<EventRow>,
+}
+
+impl EventTable {
+    /// Sums a numeric column per distinct value of another column, e.g. litres by product.
+    /// Groups keep the order in which they first appear; rows without a group are skipped.
+    pub fn totals_by(&self, group_column: &str, value_column: &str) -> Vec<(String, f64)> {
+        let (Some(group), Some(value)) = (self.column(group_column), self.column(value_column)) else {
+            return vec![];
+        };
+
+        let mut totals: Vec<(String, f64)> = vec![];
+
+        for row in &self.rows {
+            let key = match &row.values[group] {
+                Some(EventValue::Text(text)) => text.clone(),
+                Some(EventValue::Number(number)) => number.to_string(),
+                None => continue,
+            };
+            let Some(EventValue::Number(amount)) = row.values[value] else {
+                continue;
+            };
+
+            match totals.iter_mut().find(|(k, _)| *k == key) {
+                Some((_, total)) => *total += amount,
+                None => totals.push((key, amount)),
+            }
+        }
+
+        totals
+    }
+
+    pub fn column(&self, name: &str) -> Option<usize> {
+        self.columns.iter().position(|c| c == name)
+    }
+}
+
+/// Calculations evaluated for every event of an [`EventSeries`], one column each.
+#[derive(Debug, Clone, Default)]
+pub struct EventAnalysis {
+    columns: Vec<EventColumn>,
+}
+
+impl EventAnalysis {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn column(mut self, name: &str, calculation: EventCalculation) -> Self {
+        self.columns.push(EventColumn { name: name.to_string(), calculation });
+        self
+    }
+
+    pub fn duration(self, name: &str) -> Self {
+        self.column(name, EventCalculation::Duration)
+    }
+
+    pub fn totalized(self, name: &str, tag: &str) -> Self {
+        self.totalized_with(name, tag, Totalizer::default())
+    }
+
+    pub fn totalized_with(self, name: &str, tag: &str, totalizer: Totalizer) -> Self {
+        self.column(name, EventCalculation::Totalized { tag: tag.to_string(), totalizer })
+    }
+
+    pub fn average(self, name: &str, tag: &str) -> Self {
+        self.column(name, EventCalculation::Average { tag: tag.to_string() })
+    }
+
+    pub fn aggregate(self, name: &str, tag: &str, aggregate: Aggregate) -> Self {
+        self.column(name, EventCalculation::Aggregate { tag: tag.to_string(), aggregate })
+    }
+
+    /// Adds a `<name> <state>` column per state of `tag`.
+    pub fn time_in_states(self, name: &str, tag: &str) -> Self {
+        self.column(name, EventCalculation::TimeInStates { tag: tag.to_string() })
+    }
+
+    pub fn attribute(self, name: &str, attribute: &str) -> Self {
+        self.column(name, EventCalculation::Attribute { name: attribute.to_string() })
+    }
+
+    /// Evaluates every column for every event. Events that are still open are evaluated up
+    /// to the last point of the given series.
+    pub fn evaluate(&self, events: &EventSeries, series: &[&DataSeries]) -> Result<EventTable, EventError> {
+        let data_end = series.iter().filter_map(|s| s.data.last()).map(|dp| dp.timestamp).max();
+        let windows: Vec<(DateTime<Utc>, DateTime<Utc>)> = events.events.iter()
+            .map(|e| (e.start_time, e.end_time.or(data_end).unwrap_or(e.start_time)))
+            .collect();
+
+        let mut columns = vec![];
+        let mut cells: Vec<Vec<Option<EventValue>>> = vec![vec![]; events.events.len()];
+
+        for column in &self.columns {
+            if let EventCalculation::TimeInStates { tag } = &column.calculation {
+                let source = find(series, tag)?;
+                let per_event: Vec<Vec<(DataValue, TimeDelta)>> = windows.iter()
+                    .map(|(start, end)| states_in_window(source, *start, *end))
+                    .collect();
+
+                let mut states: Vec<&DataValue> = vec![];
+                for (state, _) in per_event.iter().flatten() {
+                    if !states.contains(&state) {
+                        states.push(state);
+                    }
+                }
+
+                for state in states {
+                    columns.push(format!("{} {}", column.name, source.tag.format_value(state)));
+                    for (row, durations) in cells.iter_mut().zip(&per_event) {
+                        let seconds = durations.iter().find(|(s, _)| s == state).map_or(0.0, |(_, d)| d.as_seconds_f64());
+                        row.push(Some(EventValue::Number(seconds)));
+                    }
+                }
+                continue;
+            }
+
+            columns.push(column.name.clone());
+            for ((row, event), (start, end)) in cells.iter_mut().zip(&events.events).zip(&windows) {
+                let value = match &column.calculation {
+                    EventCalculation::Duration => Some(EventValue::Number((*end - *start).as_seconds_f64())),
+                    EventCalculation::Attribute { name } => event.attributes.get(name).cloned().map(EventValue::Text),
+                    EventCalculation::Totalized { tag, totalizer } => {
+                        totalizer.total(find(series, tag)?, *start, *end).map(EventValue::Number)
+                    }
+                    EventCalculation::Average { tag } => {
+                        aggregate(find(series, tag)?, *start, *end, Aggregate::TimeWeightedAverage).map(EventValue::Number)
+                    }
+                    EventCalculation::Aggregate { tag, aggregate: kind } => {
+                        aggregate(find(series, tag)?, *start, *end, *kind).map(EventValue::Number)
+                    }
+                    EventCalculation::TimeInStates { .. } => unreachable!("expanded above"),
+                };
+                row.push(value);
+            }
+        }
+
+        let rows = events.events.iter().zip(cells).map(|(event, values)| EventRow {
+            start_time: event.start_time,
+            end_time: event.end_time,
+            values,
+        }).collect();
+
+        Ok(EventTable { columns, rows })
+    }
+}
+
+fn find<'a>(series: &[&'a DataSeries], tag: &str) -> Result<&'a DataSeries, EventError> {
+    series.iter().copied()
+        .find(|s| s.tag.name.eq_ignore_ascii_case(tag))
+        .ok_or_else(|| EventError::MissingTag(tag.to_string()))
+}
+
+fn aggregate(series: &DataSeries, start: DateTime<Utc>, end: DateTime<Utc>, aggregate: Aggregate) -> Option<f64> {
+    let interpolation = series.default_interpolation();
+    let slice = series.slice(&[start, end], Some(interpolation)).pop()?;
+
+    slice.aggregate(aggregate, &AggregateOptions::new(interpolation))
+}
+
+fn states_in_window(series: &DataSeries, start: DateTime<Utc>, end: DateTime<Utc>) -> Vec<(DataValue, TimeDelta)> {
+    match series.slice(&[start, end], Some(Interpolation::Step)).pop() {
+        Some(slice) => slice.time_in_state(&AggregateOptions::default()),
+        None => vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timeseries::testing::{at, floats, integers};
+    use crate::timeseries::{Event, EventInfo};
+    use std::collections::HashMap;
+
+    fn events(spans: &[(i64, Option<i64>)]) -> EventSeries {
+        EventSeries {
+            info: EventInfo { name: "batch".to_string() },
+            events: spans.iter()
+                .map(|&(start, end)| Event { start_time: at(start), end_time: end.map(at), attributes: HashMap::new() })
+                .collect(),
+        }
+    }
+
+    fn number(value: f64) -> Option<EventValue> {
+        Some(EventValue::Number(value))
+    }
+
+    fn column(table: &EventTable, name: &str) -> Vec<Option<EventValue>> {
+        let index = table.column(name).unwrap();
+        table.rows.iter().map(|row| row.values[index].clone()).collect()
+    }
+
+    #[test]
+    fn open_events_run_to_the_end_of_the_data() {
+        // Integer readings hold until the next one
+        let flow = integers("FT", &[(0, 10), (10, 20), (20, 30), (30, 40)]);
+        let analysis = EventAnalysis::new()
+            .duration("duration")
+            .average("average", "FT")
+            .aggregate("max", "FT", Aggregate::Max);
+
+        let table = analysis.evaluate(&events(&[(0, Some(20)), (20, None)]), &[&flow]).unwrap();
+
+        assert_eq!(table.columns, ["duration", "average", "max"]);
+        assert_eq!(column(&table, "duration"), [number(20.0), number(10.0)]);
+        assert_eq!(column(&table, "average"), [number(15.0), number(30.0)]);
+        // Windows are half-open, so the point at the end is not part of the event
+        assert_eq!(column(&table, "max"), [number(20.0), number(30.0)]);
+        assert_eq!(table.rows[1].end_time, None);
+    }
+
+    #[test]
+    fn sample_aggregates_ignore_the_value_at_the_event_start() {
+        let flow = floats("FT", &[(0, 10.0), (10, 20.0), (20, 30.0)]);
+        let analysis = EventAnalysis::new()
+            .aggregate("min", "FT", Aggregate::Min)
+            .aggregate("count", "FT", Aggregate::Count)
+            .aggregate("delta", "FT", Aggregate::Delta);
+
+        // The value in effect at 5 is 15, interpolated; it is not a sample of the event
+        let table = analysis.evaluate(&events(&[(5, Some(20))]), &[&flow]).unwrap();
+
+        assert_eq!(column(&table, "min"), [number(20.0)]);
+        assert_eq!(column(&table, "count"), [number(1.0)]);
+        assert_eq!(column(&table, "delta"), [number(5.0)]);
+    }
+
+    #[test]
+    fn time_in_states_expands_to_a_column_per_state() {
+        let mut mode = integers("MODE", &[(0, 1), (5, 2), (15, 1), (25, 3)]);
+        mode.tag.states = HashMap::from([(1, "Auto".to_string()), (2, "Manual".to_string())]);
+        let flow = floats("FT", &[(0, 1.0), (30, 1.0)]);
+
+        let analysis = EventAnalysis::new().time_in_states("mode", "MODE").duration("duration");
+        let table = analysis.evaluate(&events(&[(0, Some(20)), (20, None)]), &[&mode, &flow]).unwrap();
+
+        // States in order of first appearance, zero where an event never saw one
+        assert_eq!(table.columns, ["mode Auto", "mode Manual", "mode 3", "duration"]);
+        assert_eq!(table.rows[0].values, [number(10.0), number(10.0), number(0.0), number(20.0)]);
+        assert_eq!(table.rows[1].values, [number(5.0), number(0.0), number(5.0), number(10.0)]);
+    }
+
+    #[test]
+    fn missing_tags_are_reported() {
+        let analysis = EventAnalysis::new().average("average", "FT");
+
+        assert!(matches!(analysis.evaluate(&events(&[(0, None)]), &[]), Err(EventError::MissingTag(tag)) if tag == "FT"));
+    }
+
+    #[test]
+    fn totals_by_groups_in_order_of_appearance() {
+        let text = |t: &str| Some(EventValue::Text(t.to_string()));
+        let row = |values| EventRow { start_time: at(0), end_time: None, values };
+        let table = EventTable {
+            columns: vec!["product".to_string(), "volume".to_string(), "line".to_string()],
+            rows: vec![
+                row(vec![text("B"), number(5.0), number(1.0)]),
+                row(vec![text("A"), number(2.0), number(2.0)]),
+                row(vec![None, number(100.0), number(1.0)]),
+                row(vec![text("B"), number(1.5), number(1.0)]),
+                row(vec![text("A"), None, number(1.0)]),
+            ],
+        };
+
+        assert_eq!(table.totals_by("product", "volume"), [("B".to_string(), 6.5), ("A".to_string(), 2.0)]);
+        assert_eq!(table.totals_by("line", "volume"), [("1".to_string(), 106.5), ("2".to_string(), 2.0)]);
+        assert!(table.totals_by("product", "mass").is_empty());
+    }
+}
@@ -0,0 +1,368 @@
+use super::{BoundaryPolicy, DataPoint, DataSeries, DataValue};
+use chrono::{DateTime, TimeDelta, Utc};
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EventInfo {
+    pub name: String
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Event {
+    pub start_time: DateTime<Utc>,
+    pub end_time: Option<DateTime<Utc>>,
+    pub attributes: HashMap<String, String>
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EventSeries {
+    pub info: EventInfo,
+    pub events: Vec<Event>
+}
+
+#[derive(Error, Debug)]
+pub enum EventError {
+    #[error("no data series for tag \"{0}\"")]
+    MissingTag(String),
+}
+
+/// A test applied to a single tag value.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Condition {
+    Equals(DataValue),
+    NotEquals(DataValue),
+    GreaterThan(f64),
+    GreaterOrEqual(f64),
+    LessThan(f64),
+    LessOrEqual(f64),
+    OneOf(Vec<DataValue>),
+}
+
+impl Condition {
+    pub fn matches(&self, value: &DataValue) -> bool {
+        let number = || value.as_f64();
+
+        match self {
+            Condition::Equals(expected) => values_equal(value, expected),
+            Condition::NotEquals(expected) => !values_equal(value, expected),
+            Condition::GreaterThan(limit) => number().is_some_and(|v| v > *limit),
+            Condition::GreaterOrEqual(limit) => number().is_some_and(|v| v >= *limit),
+            Condition::LessThan(limit) => number().is_some_and(|v| v < *limit),
+            Condition::LessOrEqual(limit) => number().is_some_and(|v| v <= *limit),
+            Condition::OneOf(expected) => expected.iter().any(|e| values_equal(value, e)),
+        }
+    }
+}
+
+// Numbers compare by value so that Integer(1) equals Float(1.0)
+fn values_equal(a: &DataValue, b: &DataValue) -> bool {
+    match (a, b) {
+        (DataValue::Text(a), DataValue::Text(b)) => a == b,
+        _ => a.as_f64().is_some() && a.as_f64() == b.as_f64(),
+    }
+}
+
+/// What opens or closes an event.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Trigger {
+    /// Fires whenever the tag takes a new value.
+    ValueChange { tag: String },
+    /// Fires when the condition on the tag becomes true.
+    Condition { tag: String, condition: Condition },
+}
+
+impl Trigger {
+    pub fn value_change(tag: &str) -> Self {
+        Trigger::ValueChange { tag: tag.to_string() }
+    }
+
+    pub fn condition(tag: &str, condition: Condition) -> Self {
+        Trigger::Condition { tag: tag.to_string(), condition }
+    }
+
+    /// The tag the trigger watches.
+    pub fn tag(&self) -> &str {
+        match self {
+            Trigger::ValueChange { tag } | Trigger::Condition { tag, .. } => tag,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CaptureAt {
+    /// The value in effect when the event opens.
+    Start,
+    /// The last value before the event closes.
+    End,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AttributeCapture {
+    pub name: String,
+    pub tag: String,
+    pub at: CaptureAt,
+}
+
+/// Describes how events are derived from tag data.
+///
+/// An event opens when the start trigger fires. Without an end trigger, an event opened by a
+/// value change closes at the next change (which opens the next event), and an event opened
+/// by a condition closes when the condition stops holding. With an end trigger, the event
+/// closes when that fires, or when the start trigger fires again, which opens the next event.
+/// Points with bad quality or no value are ignored.
+#[derive(Debug, Clone, Deserialize)]
+pub struct EventDefinition {
+    pub name: String,
+    pub start: Trigger,
+    #[serde(default)]
+    pub end: Option<Trigger>,
+    #[serde(default)]
+    pub attributes: Vec<AttributeCapture>,
+}
+
+impl EventDefinition {
+    pub fn new(name: &str, start: Trigger) -> Self {
+        Self {
+            name: name.to_string(),
+            start,
+            end: None,
+            attributes: vec![],
+        }
+    }
+
+    pub fn end(mut self, end: Trigger) -> Self {
+        self.end = Some(end);
+        self
+    }
+
+    /// Records the value of `tag` as the attribute `name` of every event. Integer values of
+    /// tags with state texts are replaced by the text.
+    pub fn attribute(mut self, name: &str, tag: &str, at: CaptureAt) -> Self {
+        self.attributes.push(AttributeCapture {
+            name: name.to_string(),
+            tag: tag.to_string(),
+            at,
+        });
+        self
+    }
+
+    /// Derives the events from the given series, which must include every tag the
+    /// definition refers to. An event still open at the end of the data has no end time.
+    pub fn detect(&self, series: &[&DataSeries]) -> Result<EventSeries, EventError> {
+        let find = |tag: &str| {
+            series.iter().copied()
+                .find(|s| s.tag.name.eq_ignore_ascii_case(tag))
+                .ok_or_else(|| EventError::MissingTag(tag.to_string()))
+        };
+
+        let start_series = find(self.start.tag())?;
+        let end_series = self.end.as_ref().map(|end| find(end.tag())).transpose()?;
+
+        // Visit trigger points in time order. At equal times the end trigger goes first so an
+        // event can close and the next one open on the same point.
+        let mut timeline: Vec<(DateTime<Utc>, Source, &DataPoint)> = usable(start_series)
+            .map(|dp| (dp.timestamp, Source::Start, dp))
+            .chain(end_series.into_iter().flat_map(usable).map(|dp| (dp.timestamp, Source::End, dp)))
+            .collect();
+        timeline.sort_by_key(|(timestamp, source, _)| (*timestamp, *source));
+
+        let mut events: Vec<Event> = vec![];
+        let mut open: Option<DateTime<Utc>> = None;
+        let mut start_state = TriggerState::default();
+        let mut end_state = TriggerState::default();
+
+        for (timestamp, source, point) in timeline {
+            let value = point.value.as_ref().expect("usable points have a value");
+
+            match source {
+                Source::End => {
+                    let end = self.end.as_ref().expect("end points only exist with an end trigger");
+                    let fired = end_state.update(end, value);
+                    if let Some(started) = open.filter(|started| fired && *started < timestamp) {
+                        events.push(Event { start_time: started, end_time: Some(timestamp), attributes: HashMap::new() });
+                        open = None;
+                    }
+                }
+                Source::Start => {
+                    let was_active = start_state.active;
+                    let fired = start_state.update(&self.start, value);
+
+                    if let Some(started) = open {
+                        // The start trigger firing again closes the event; without an end
+                        // trigger, so does the start condition no longer holding
+                        let closes = match (&self.start, &self.end) {
+                            (Trigger::ValueChange { .. }, _) => fired,
+                            (Trigger::Condition { .. }, None) => was_active && !start_state.active,
+                            (Trigger::Condition { .. }, Some(_)) => fired,
+                        };
+
+                        if closes && started < timestamp {
+                            events.push(Event { start_time: started, end_time: Some(timestamp), attributes: HashMap::new() });
+                            open = None;
+                        }
+                    }
+
+                    if fired && open.is_none() {
+                        open = Some(timestamp);
+                    }
+                }
+            }
+        }
+
+        if let Some(started) = open {
+            events.push(Event { start_time: started, end_time: None, attributes: HashMap::new() });
+        }
+
+        for capture in &self.attributes {
+            let source = find(&capture.tag)?;
+
+            for event in events.iter_mut() {
+                let point = match capture.at {
+                    CaptureAt::Start => source.value_at_or_before(event.start_time, BoundaryPolicy::None),
+                    CaptureAt::End => match event.end_time {
+                        Some(end) => source.value_at_or_before(end - TimeDelta::nanoseconds(1), BoundaryPolicy::None),
+                        None => source.data.last(),
+                    },
+                };
+
+                if let Some(value) = point.and_then(|p| p.value.as_ref()) {
+                    event.attributes.insert(capture.name.clone(), source.tag.format_value(value));
+                }
+            }
+        }
+
+        Ok(EventSeries {
+            info: EventInfo { name: self.name.clone() },
+            events,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Source {
+    End,
+    Start,
+}
+
+#[derive(Default)]
+struct TriggerState {
+    last: Option<DataValue>,
+    active: bool,
+}
+
+impl TriggerState {
+    // Feeds the next value of the trigger's tag and returns whether the trigger fired
+    fn update(&mut self, trigger: &Trigger, value: &DataValue) -> bool {
+        let fired = match trigger {
+            Trigger::ValueChange { .. } => !self.last.as_ref().is_some_and(|last| values_equal(last, value)),
+            Trigger::Condition { condition, .. } => {
+                let active = condition.matches(value);
+                let fired = active && !self.active;
+                self.active = active;
+                fired
+            }
+        };
+
+        self.last = Some(value.clone());
+        fired
+    }
+}
+
+fn usable(series: &DataSeries) -> impl Iterator<Item = &DataPoint> {
+    series.data.iter().filter(|dp| dp.value.is_some() && dp.quality.is_usable())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timeseries::testing::{at, integers};
+    use crate::timeseries::DataQuality;
+
+    // Start and end of each event in seconds
+    fn spans(events: &EventSeries) -> Vec<(i64, Option<i64>)> {
+        events.events.iter()
+            .map(|e| ((e.start_time - at(0)).num_seconds(), e.end_time.map(|end| (end - at(0)).num_seconds())))
+            .collect()
+    }
+
+    #[test]
+    fn value_change_opens_an_event_per_new_value() {
+        let mut batch = integers("BATCH", &[(0, 1), (10, 1), (20, 2), (25, 9), (30, 3)]);
+        // Bad points are ignored
+        batch.data[3].quality = DataQuality::BAD;
+
+        let events = EventDefinition::new("batch", Trigger::value_change("batch")).detect(&[&batch]).unwrap();
+
+        assert_eq!(events.info.name, "batch");
+        assert_eq!(spans(&events), [(0, Some(20)), (20, Some(30)), (30, None)]);
+    }
+
+    #[test]
+    fn condition_holds_the_event_open() {
+        let flow = integers("FT", &[(0, 0), (10, 5), (20, 7), (30, 1), (40, 6)]);
+        let definition = EventDefinition::new("flowing", Trigger::condition("FT", Condition::GreaterThan(4.0)));
+
+        // Open until the data ends
+        assert_eq!(spans(&definition.detect(&[&flow]).unwrap()), [(10, Some(30)), (40, None)]);
+    }
+
+    #[test]
+    fn end_and_start_triggers_on_the_same_point() {
+        let definition = EventDefinition::new("run", Trigger::condition("RUN", Condition::Equals(DataValue::Integer(1))))
+            .end(Trigger::condition("DONE", Condition::Equals(DataValue::Integer(1))));
+
+        // Both fire at 30: the first run ends there and the next one starts on the same point
+        let run = integers("RUN", &[(0, 1), (10, 0), (30, 1)]);
+        let done = integers("DONE", &[(0, 0), (30, 1), (40, 0)]);
+        assert_eq!(spans(&definition.detect(&[&run, &done]).unwrap()), [(0, Some(30)), (30, None)]);
+
+        // Nothing is open at 10, so the run starting there is not closed at once
+        let run = integers("RUN", &[(0, 0), (10, 1)]);
+        let done = integers("DONE", &[(0, 0), (10, 1)]);
+        assert_eq!(spans(&definition.detect(&[&run, &done]).unwrap()), [(10, None)]);
+    }
+
+    #[test]
+    fn start_trigger_closes_an_open_event() {
+        let run = integers("RUN", &[(0, 1), (10, 0), (20, 1), (30, 0)]);
+        let done = integers("DONE", &[(0, 0), (35, 1)]);
+        let definition = EventDefinition::new("run", Trigger::condition("RUN", Condition::Equals(DataValue::Integer(1))))
+            .end(Trigger::condition("DONE", Condition::Equals(DataValue::Integer(1))));
+
+        // RUN dropping to 0 does not close a run with an end trigger; RUN starting again does
+        assert_eq!(spans(&definition.detect(&[&run, &done]).unwrap()), [(0, Some(20)), (20, Some(35))]);
+    }
+
+    #[test]
+    fn attributes_are_captured_at_start_and_end() {
+        let batch = integers("BATCH", &[(0, 1), (20, 2)]);
+        let level = integers("LT", &[(0, 1), (15, 2), (20, 3), (25, 4)]);
+        let mut mode = integers("MODE", &[(0, 1), (10, 2), (20, 3)]);
+        mode.tag.states = HashMap::from([(1, "Auto".to_string()), (2, "Manual".to_string())]);
+
+        let definition = EventDefinition::new("batch", Trigger::value_change("BATCH"))
+            .attribute("level", "LT", CaptureAt::End)
+            .attribute("mode", "MODE", CaptureAt::Start);
+        let events = definition.detect(&[&batch, &level, &mode]).unwrap();
+        let attributes: Vec<_> = events.events.iter()
+            .map(|e| (e.attributes["level"].as_str(), e.attributes["mode"].as_str()))
+            .collect();
+
+        // The end value is the last before the event closes; without a state text the
+        // number is kept
+        assert_eq!(attributes, [("2", "Auto"), ("4", "3")]);
+    }
+
+    #[test]
+    fn missing_tags_are_reported() {
+        let batch = integers("BATCH", &[(0, 1)]);
+        let definition = EventDefinition::new("batch", Trigger::value_change("BATCH")).attribute("mode", "MODE", CaptureAt::Start);
+
+        assert!(matches!(definition.detect(&[&batch]), Err(EventError::MissingTag(tag)) if tag == "MODE"));
+    }
+}
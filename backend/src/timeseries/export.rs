@@ -0,0 +1,291 @@
+use super::{DataSeries, DataValue, WideTable};
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, SecondsFormat, Utc};
+use chrono_tz::Tz;
+use std::collections::HashMap;
+use std::io;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ExportError {
+    #[error("invalid timestamp format \"{0}\"")]
+    InvalidTimestampFormat(String),
+}
+
+/// How series and tables are written as CSV.
+#[derive(Debug, Clone)]
+pub struct CsvOptions {
+    delimiter: u8,
+    timestamp_format: Option<String>,
+    timezone: Tz,
+    decimals: Option<usize>,
+    state_text: bool,
+    header: bool,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        Self {
+            delimiter: b',',
+            timestamp_format: None,
+            timezone: Tz::UTC,
+            decimals: None,
+            state_text: true,
+            header: true,
+        }
+    }
+}
+
+impl CsvOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the field delimiter, e.g. `b';'` or `b'\t'`. Defaults to a comma.
+    pub fn delimiter(mut self, delimiter: u8) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    /// Formats timestamps with a `strftime` pattern such as `%Y-%m-%d %H:%M:%S`. Defaults to
+    /// RFC 3339 with milliseconds. Fails for patterns chrono cannot format, e.g. `%Q`.
+    pub fn timestamp_format(mut self, format: &str) -> Result<Self, ExportError> {
+        // Formatting with an invalid pattern panics, so it is checked here
+        if StrftimeItems::new(format).any(|item| matches!(item, Item::Error)) {
+            return Err(ExportError::InvalidTimestampFormat(format.to_string()));
+        }
+
+        self.timestamp_format = Some(format.to_string());
+        Ok(self)
+    }
+
+    /// Writes timestamps in a time zone such as `chrono_tz::America::Chicago`. Defaults to UTC.
+    pub fn timezone(mut self, timezone: Tz) -> Self {
+        self.timezone = timezone;
+        self
+    }
+
+    /// Writes every number with a fixed number of decimals. By default the decimals come
+    /// from each tag's format, and numbers of tags without one are written in full.
+    pub fn decimals(mut self, decimals: usize) -> Self {
+        self.decimals = Some(decimals);
+        self
+    }
+
+    /// Writes the state text of tags with states in place of the integer value. Defaults to true.
+    pub fn state_text(mut self, state_text: bool) -> Self {
+        self.state_text = state_text;
+        self
+    }
+
+    /// Starts the output with a header row that includes units. Defaults to true.
+    pub fn header(mut self, header: bool) -> Self {
+        self.header = header;
+        self
+    }
+
+    fn writer<W: io::Write>(&self, writer: W) -> csv::Writer<W> {
+        csv::WriterBuilder::new().delimiter(self.delimiter).from_writer(writer)
+    }
+
+    fn timestamp(&self, timestamp: DateTime<Utc>) -> String {
+        let local = timestamp.with_timezone(&self.timezone);
+
+        match &self.timestamp_format {
+            Some(format) => local.format(format).to_string(),
+            None => local.to_rfc3339_opts(SecondsFormat::Millis, true),
+        }
+    }
+
+    fn value(&self, value: Option<&DataValue>, format: Option<&str>, states: &HashMap<i32, String>) -> String {
+        match value {
+            None => String::new(),
+            Some(DataValue::Integer(v)) if self.state_text => match states.get(v) {
+                Some(text) => text.clone(),
+                None => v.to_string(),
+            },
+            Some(DataValue::Float(v)) => match self.decimals.or_else(|| format.and_then(decimals)) {
+                Some(decimals) => format!("{:.*}", decimals, v),
+                None => v.to_string(),
+            },
+            Some(value) => value.to_string(),
+        }
+    }
+}
+
+impl DataSeries {
+    /// Writes the series as `Timestamp`, `name [uom]` and `Quality` columns.
+    pub fn write_csv<W: io::Write>(&self, writer: W, options: &CsvOptions) -> csv::Result<()> {
+        let mut csv = options.writer(writer);
+
+        if options.header {
+            let value = match &self.tag.uom {
+                Some(uom) => format!("{} [{}]", self.tag.name, uom),
+                None => self.tag.name.clone(),
+            };
+            csv.write_record(["Timestamp", value.as_str(), "Quality"])?;
+        }
+
+        for point in &self.data {
+            csv.write_record([
+                options.timestamp(point.timestamp),
+                options.value(point.value.as_ref(), self.tag.format.as_deref(), &self.tag.states),
+                point.quality.to_string(),
+            ])?;
+        }
+
+        csv.flush()?;
+        Ok(())
+    }
+}
+
+impl WideTable {
+    /// Writes the table with the columns of [`WideTable::headers`].
+    pub fn write_csv<W: io::Write>(&self, writer: W, options: &CsvOptions) -> csv::Result<()> {
+        let mut csv = options.writer(writer);
+
+        if options.header {
+            csv.write_record(self.headers())?;
+        }
+
+        for row in &self.rows {
+            let mut record = vec![options.timestamp(row.timestamp)];
+
+            record.extend(self.columns.iter().zip(&row.values).map(|(column, value)| {
+                options.value(value.as_ref(), column.format.as_deref(), &column.states)
+            }));
+
+            if let Some(qualities) = &row.qualities {
+                record.extend(qualities.iter().map(|q| q.map(|q| q.to_string()).unwrap_or_default()));
+            }
+
+            csv.write_record(&record)?;
+        }
+
+        csv.flush()?;
+        Ok(())
+    }
+}
+
+// Number of decimals in a tag format. Accepts .NET style `F2`/`N2`, picture formats such as
+// `#,##0.00` and printf style `%.2f`.
+fn decimals(format: &str) -> Option<usize> {
+    let format = format.trim();
+    if format.is_empty() {
+        return None;
+    }
+
+    if let Some(digits) = format.strip_prefix(['F', 'f', 'N', 'n']) {
+        return digits.parse().ok();
+    }
+
+    if let Some(printf) = format.strip_prefix('%') {
+        let precision = printf.split_once('.')?.1;
+        let digits = precision.len() - precision.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        return precision[..digits].parse().ok();
+    }
+
+    let (whole, fraction) = format.split_once('.').unwrap_or((format, ""));
+    if !whole.chars().all(|c| matches!(c, '#' | '0' | ',')) {
+        return None;
+    }
+
+    Some(fraction.chars().take_while(|c| matches!(c, '#' | '0')).count())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timeseries::testing::{floats, integers};
+    use crate::timeseries::{align, AlignOptions};
+
+    fn series_csv(series: &DataSeries, options: &CsvOptions) -> String {
+        let mut out = vec![];
+        series.write_csv(&mut out, options).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn decimals_reads_tag_formats() {
+        assert_eq!(decimals("F2"), Some(2));
+        assert_eq!(decimals("n0"), Some(0));
+        assert_eq!(decimals("#,##0.00"), Some(2));
+        assert_eq!(decimals("0.0##"), Some(3));
+        assert_eq!(decimals("0"), Some(0));
+        assert_eq!(decimals("%.2f"), Some(2));
+        assert_eq!(decimals("%8.3lf"), Some(3));
+
+        assert_eq!(decimals(""), None);
+        assert_eq!(decimals("%d"), None);
+        assert_eq!(decimals("Fixed"), None);
+        assert_eq!(decimals("General"), None);
+    }
+
+    #[test]
+    fn series_header_has_units_and_values_follow_the_tag_format() {
+        let mut flow = floats("FT", &[(0, 1.234), (60, 2.75)]);
+        flow.tag.uom = Some("m3/h".to_string());
+        flow.tag.format = Some("F2".to_string());
+
+        assert_eq!(series_csv(&flow, &CsvOptions::new()), "\
+            Timestamp,FT [m3/h],Quality\n\
+            2025-01-01T00:00:00.000Z,1.23,Good\n\
+            2025-01-01T00:01:00.000Z,2.75,Good\n");
+
+        // The option overrides the tag format
+        let options = CsvOptions::new().decimals(0).header(false);
+        assert_eq!(series_csv(&flow, &options), "\
+            2025-01-01T00:00:00.000Z,1,Good\n\
+            2025-01-01T00:01:00.000Z,3,Good\n");
+
+        // Without a format numbers are written in full
+        flow.tag.format = None;
+        assert_eq!(series_csv(&flow, &CsvOptions::new().header(false)), "\
+            2025-01-01T00:00:00.000Z,1.234,Good\n\
+            2025-01-01T00:01:00.000Z,2.75,Good\n");
+    }
+
+    #[test]
+    fn state_text_replaces_integer_states() {
+        let mut pump = integers("PUMP", &[(0, 0), (10, 1), (20, 2)]);
+        pump.tag.states = HashMap::from([(0, "Off".to_string()), (1, "On".to_string())]);
+        let options = CsvOptions::new().header(false);
+
+        let values = |options: &CsvOptions| -> Vec<String> {
+            series_csv(&pump, options).lines().map(|line| line.split(',').nth(1).unwrap().to_string()).collect()
+        };
+
+        // Values without a state are written as numbers
+        assert_eq!(values(&options), ["Off", "On", "2"]);
+        assert_eq!(values(&options.state_text(false)), ["0", "1", "2"]);
+    }
+
+    #[test]
+    fn timestamps_use_the_time_zone_and_format() {
+        let flow = floats("FT", &[(0, 1.0)]);
+
+        let options = CsvOptions::new().header(false).timezone(chrono_tz::America::Chicago);
+        assert_eq!(series_csv(&flow, &options), "2024-12-31T18:00:00.000-06:00,1,Good\n");
+
+        let options = options.timestamp_format("%Y-%m-%d %H:%M").unwrap().delimiter(b';');
+        assert_eq!(series_csv(&flow, &options), "2024-12-31 18:00;1;Good\n");
+
+        assert!(matches!(CsvOptions::new().timestamp_format("%Q"), Err(ExportError::InvalidTimestampFormat(_))));
+    }
+
+    #[test]
+    fn table_writes_headers_with_units_and_quality_columns() {
+        let mut flow = floats("FT", &[(0, 1.5), (10, 2.5)]);
+        flow.tag.uom = Some("m3/h".to_string());
+        let level = floats("LT", &[(10, 40.0)]);
+
+        let table = align(&[&flow, &level], &AlignOptions::default().quality_columns(true));
+        let mut out = vec![];
+        table.write_csv(&mut out, &CsvOptions::new()).unwrap();
+
+        assert_eq!(String::from_utf8(out).unwrap(), "\
+            Timestamp,FT [m3/h],LT,FT quality,LT quality\n\
+            2025-01-01T00:00:00.000Z,1.5,,Good,\n\
+            2025-01-01T00:00:10.000Z,2.5,40,Good,Good\n");
+    }
+}
@@ -0,0 +1,222 @@
+use super::{aggregate, AggregateOptions, DataSeries, Interpolation, QualityFilter, Totalizer};
+use chrono::{DateTime, TimeDelta, Utc};
+
+/// The time unit of a rate, e.g. hours for a flow in `m3/h`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeBase {
+    PerSecond,
+    PerMinute,
+    PerHour,
+}
+
+impl TimeBase {
+    pub fn seconds(&self) -> f64 {
+        match self {
+            TimeBase::PerSecond => 1.0,
+            TimeBase::PerMinute => 60.0,
+            TimeBase::PerHour => 3600.0,
+        }
+    }
+
+    /// Reads the time base from the denominator of a unit of measure, e.g. `m3/h`.
+    pub fn from_uom(uom: &str) -> Option<Self> {
+        let (_, per) = uom.rsplit_once('/')?;
+
+        match per.trim().to_lowercase().as_str() {
+            "s" | "sec" => Some(TimeBase::PerSecond),
+            "m" | "min" => Some(TimeBase::PerMinute),
+            "h" | "hr" => Some(TimeBase::PerHour),
+            _ => None,
+        }
+    }
+}
+
+/// Integrates rate tags, such as flows, into totals.
+#[derive(Debug, Clone, Copy)]
+pub struct Integrator {
+    time_base: TimeBase,
+    interpolation: Interpolation,
+    quality: QualityFilter,
+}
+
+impl Integrator {
+    /// Trapezoidal integration of a rate in units per `time_base`.
+    pub fn new(time_base: TimeBase) -> Self {
+        Self {
+            time_base,
+            interpolation: Interpolation::Linear,
+            quality: QualityFilter::Usable,
+        }
+    }
+
+    /// Trapezoidal with [`Interpolation::Linear`], or step with [`Interpolation::Step`], where
+    /// each rate holds until the next point.
+    pub fn interpolation(mut self, interpolation: Interpolation) -> Self {
+        self.interpolation = interpolation;
+        self
+    }
+
+    pub fn quality(mut self, quality: QualityFilter) -> Self {
+        self.quality = quality;
+        self
+    }
+
+    /// The integral of the rate from `start` to `end`. Time without an accepted value, such
+    /// as before the first point, adds nothing. `None` if no time had a value.
+    pub fn integrate(&self, rate: &DataSeries, start: DateTime<Utc>, end: DateTime<Utc>) -> Option<f64> {
+        if end <= start {
+            return None;
+        }
+
+        let slices = rate.slice(&[start, end], Some(self.interpolation));
+        let slice = slices.first()?;
+
+        // A closing point at `end` lets the last trapezoid stop at the right height
+        let closing = rate.boundary_point(rate.data.partition_point(|dp| dp.timestamp <= end), end, self.interpolation);
+        let options = AggregateOptions::new(self.interpolation).quality(self.quality).window(start, end);
+
+        let (area, seconds) = aggregate::area(slice.points().chain(closing.iter()), &options)?;
+
+        (seconds > 0.0).then(|| area / self.time_base.seconds())
+    }
+
+    /// Compares the integrated rate with the increase of the matching counter tag.
+    pub fn reconcile(&self, rate: &DataSeries, counter: &DataSeries, totalizer: &Totalizer, start: DateTime<Utc>, end: DateTime<Utc>) -> Reconciliation {
+        Reconciliation {
+            integrated: self.integrate(rate, start, end),
+            totalized: totalizer.total(counter, start, end),
+        }
+    }
+
+    /// The counter's increase from `start` to `end`, with the periods where the counter has no
+    /// readings at most `max_gap` apart filled in from the integrated rate.
+    pub fn fill_gaps(&self, rate: &DataSeries, counter: &DataSeries, totalizer: &Totalizer, start: DateTime<Utc>, end: DateTime<Utc>, max_gap: TimeDelta) -> Option<f64> {
+        let mut parts = vec![];
+        let mut from = start;
+
+        for (gap_start, gap_end) in totalizer.gaps(counter, start, end, max_gap) {
+            if gap_start > from {
+                parts.push(totalizer.total(counter, from, gap_start));
+            }
+            parts.push(self.integrate(rate, gap_start, gap_end));
+            from = gap_end;
+        }
+
+        if from < end {
+            parts.push(totalizer.total(counter, from, end));
+        }
+
+        parts.into_iter().flatten().reduce(|a, b| a + b)
+    }
+}
+
+/// A total worked out from both a rate tag and its counter tag.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Reconciliation {
+    pub integrated: Option<f64>,
+    pub totalized: Option<f64>,
+}
+
+impl Reconciliation {
+    /// Totalized minus integrated.
+    pub fn difference(&self) -> Option<f64> {
+        Some(self.totalized? - self.integrated?)
+    }
+
+    /// The difference as a fraction of the totalized value.
+    pub fn relative_difference(&self) -> Option<f64> {
+        let totalized = self.totalized.filter(|t| *t != 0.0)?;
+
+        Some(self.difference()? / totalized)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timeseries::testing::{at, floats};
+
+    fn close(actual: Option<f64>, expected: f64) -> bool {
+        actual.is_some_and(|actual| (actual - expected).abs() < 1e-9)
+    }
+
+    #[test]
+    fn time_base_from_uom() {
+        assert_eq!(TimeBase::from_uom("m3/h"), Some(TimeBase::PerHour));
+        assert_eq!(TimeBase::from_uom("kg / min"), Some(TimeBase::PerMinute));
+        assert_eq!(TimeBase::from_uom("l/S"), Some(TimeBase::PerSecond));
+        assert_eq!(TimeBase::from_uom("m3"), None);
+        assert_eq!(TimeBase::from_uom("m3/d"), None);
+    }
+
+    #[test]
+    fn last_trapezoid_closes_at_the_end() {
+        let rate = floats("FT", &[(0, 0.0), (10, 10.0), (20, 30.0)]);
+        let integrator = Integrator::new(TimeBase::PerSecond);
+
+        // 0 to 10 over 10s, then 10 to 20 (interpolated at 15) over 5s
+        assert!(close(integrator.integrate(&rate, at(0), at(15)), 125.0));
+        // The window starts on the interpolated value 5
+        assert!(close(integrator.integrate(&rate, at(5), at(15)), 112.5));
+    }
+
+    #[test]
+    fn step_holds_each_rate() {
+        let rate = floats("FT", &[(0, 0.0), (10, 10.0), (20, 30.0)]);
+        let integrator = Integrator::new(TimeBase::PerSecond).interpolation(Interpolation::Step);
+
+        assert!(close(integrator.integrate(&rate, at(0), at(15)), 50.0));
+        // 0 from 5, 10 for 10s, then 30 for 5s
+        assert!(close(integrator.integrate(&rate, at(5), at(25)), 250.0));
+    }
+
+    #[test]
+    fn rate_is_divided_by_its_time_base() {
+        let rate = floats("FT", &[(0, 60.0), (120, 60.0)]);
+
+        assert!(close(Integrator::new(TimeBase::PerSecond).integrate(&rate, at(0), at(120)), 7200.0));
+        assert!(close(Integrator::new(TimeBase::PerMinute).integrate(&rate, at(0), at(120)), 120.0));
+        assert!(close(Integrator::new(TimeBase::PerHour).integrate(&rate, at(0), at(120)), 2.0));
+    }
+
+    #[test]
+    fn time_without_a_value_adds_nothing() {
+        let rate = floats("FT", &[(10, 10.0), (20, 10.0)]);
+        let integrator = Integrator::new(TimeBase::PerSecond);
+
+        assert!(close(integrator.integrate(&rate, at(0), at(20)), 100.0));
+        assert_eq!(integrator.integrate(&rate, at(0), at(5)), None);
+        assert_eq!(integrator.integrate(&rate, at(20), at(20)), None);
+        assert_eq!(integrator.integrate(&floats("FT", &[]), at(0), at(20)), None);
+    }
+
+    #[test]
+    fn reconcile_compares_with_the_counter() {
+        let rate = floats("FT", &[(0, 0.0), (10, 10.0), (20, 30.0)]);
+        let counter = floats("FQ", &[(0, 100.0), (20, 300.0)]);
+
+        let reconciliation = Integrator::new(TimeBase::PerSecond).reconcile(&rate, &counter, &Totalizer::new(), at(0), at(15));
+
+        assert!(close(reconciliation.integrated, 125.0));
+        assert!(close(reconciliation.totalized, 150.0));
+        assert!(close(reconciliation.difference(), 25.0));
+        assert!(close(reconciliation.relative_difference(), 25.0 / 150.0));
+    }
+
+    #[test]
+    fn fill_gaps_adds_counter_and_rate_pieces() {
+        let rate = floats("FT", &(0..=10).map(|i| (i * 10, 10.0)).collect::<Vec<_>>());
+        // The counter misses its readings from 10 to 60 and after 70
+        let counter = floats("FQ", &[(0, 0.0), (10, 100.0), (60, 300.0), (70, 400.0)]);
+        let integrator = Integrator::new(TimeBase::PerSecond);
+        let totalizer = Totalizer::new();
+
+        // Counter 0..10 (100), rate 10..60 (500), counter 60..70 (100), rate 70..100 (300)
+        let filled = integrator.fill_gaps(&rate, &counter, &totalizer, at(0), at(100), TimeDelta::seconds(15));
+        assert!(close(filled, 1000.0));
+
+        // Without gaps it is just the counter total
+        let filled = integrator.fill_gaps(&rate, &counter, &totalizer, at(0), at(10), TimeDelta::seconds(15));
+        assert!(close(filled, 100.0));
+    }
+}
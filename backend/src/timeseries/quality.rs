@@ -0,0 +1,197 @@
+use serde::Serialize;
+use std::fmt;
+
+/// OPC quality word as stored by Timebase.
+///
+/// The low byte has the layout `QQSSSSLL`: two bits of major quality, four bits of substatus
+/// and two limit bits. The high byte is vendor specific and kept as-is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+pub struct DataQuality(pub i16);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum QualityMajor {
+    Good,
+    Uncertain,
+    Bad,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum QualitySubstatus {
+    NonSpecific,
+    // Bad
+    ConfigurationError,
+    NotConnected,
+    DeviceFailure,
+    SensorFailure,
+    LastKnownValue,
+    CommFailure,
+    OutOfService,
+    WaitingForInitialData,
+    // Uncertain
+    LastUsableValue,
+    SensorNotAccurate,
+    EngineeringUnitsExceeded,
+    SubNormal,
+    // Good
+    LocalOverride,
+    /// A substatus code not defined for the major quality.
+    Other(u8),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum QualityLimit {
+    NotLimited,
+    Low,
+    High,
+    Constant,
+}
+
+impl DataQuality {
+    pub const GOOD: DataQuality = DataQuality(0xC0);
+    pub const UNCERTAIN: DataQuality = DataQuality(0x40);
+    pub const BAD: DataQuality = DataQuality(0x00);
+
+    pub fn raw(&self) -> i16 {
+        self.0
+    }
+
+    /// The major quality. The reserved `10` pattern is treated as bad.
+    pub fn major(&self) -> QualityMajor {
+        match (self.0 >> 6) & 0b11 {
+            0b11 => QualityMajor::Good,
+            0b01 => QualityMajor::Uncertain,
+            _ => QualityMajor::Bad,
+        }
+    }
+
+    pub fn substatus(&self) -> QualitySubstatus {
+        let code = ((self.0 >> 2) & 0b1111) as u8;
+
+        match (self.major(), code) {
+            (_, 0) => QualitySubstatus::NonSpecific,
+            (QualityMajor::Bad, 1) => QualitySubstatus::ConfigurationError,
+            (QualityMajor::Bad, 2) => QualitySubstatus::NotConnected,
+            (QualityMajor::Bad, 3) => QualitySubstatus::DeviceFailure,
+            (QualityMajor::Bad, 4) => QualitySubstatus::SensorFailure,
+            (QualityMajor::Bad, 5) => QualitySubstatus::LastKnownValue,
+            (QualityMajor::Bad, 6) => QualitySubstatus::CommFailure,
+            (QualityMajor::Bad, 7) => QualitySubstatus::OutOfService,
+            (QualityMajor::Bad, 8) => QualitySubstatus::WaitingForInitialData,
+            (QualityMajor::Uncertain, 1) => QualitySubstatus::LastUsableValue,
+            (QualityMajor::Uncertain, 4) => QualitySubstatus::SensorNotAccurate,
+            (QualityMajor::Uncertain, 5) => QualitySubstatus::EngineeringUnitsExceeded,
+            (QualityMajor::Uncertain, 6) => QualitySubstatus::SubNormal,
+            (QualityMajor::Good, 6) => QualitySubstatus::LocalOverride,
+            (_, code) => QualitySubstatus::Other(code),
+        }
+    }
+
+    pub fn limit(&self) -> QualityLimit {
+        match self.0 & 0b11 {
+            0b01 => QualityLimit::Low,
+            0b10 => QualityLimit::High,
+            0b11 => QualityLimit::Constant,
+            _ => QualityLimit::NotLimited,
+        }
+    }
+
+    /// The vendor specific high byte.
+    pub fn vendor_bits(&self) -> u8 {
+        (self.0 >> 8) as u8
+    }
+
+    pub fn is_good(&self) -> bool {
+        self.major() == QualityMajor::Good
+    }
+
+    pub fn is_uncertain(&self) -> bool {
+        self.major() == QualityMajor::Uncertain
+    }
+
+    pub fn is_bad(&self) -> bool {
+        self.major() == QualityMajor::Bad
+    }
+
+    /// True for good and uncertain values, which calculations may still use.
+    pub fn is_usable(&self) -> bool {
+        !self.is_bad()
+    }
+
+    /// True if the value is pinned at a limit and does not follow the process.
+    pub fn is_limited(&self) -> bool {
+        self.limit() != QualityLimit::NotLimited
+    }
+}
+
+impl From<i16> for DataQuality {
+    fn from(raw: i16) -> Self {
+        DataQuality(raw)
+    }
+}
+
+impl fmt::Display for DataQuality {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.major())?;
+
+        if self.substatus() != QualitySubstatus::NonSpecific {
+            write!(f, " ({:?})", self.substatus())?;
+        }
+
+        if self.is_limited() {
+            write!(f, " [{:?} limited]", self.limit())?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_major_quality() {
+        assert_eq!(DataQuality(0xC0).major(), QualityMajor::Good);
+        assert_eq!(DataQuality(0x40).major(), QualityMajor::Uncertain);
+        assert_eq!(DataQuality(0x00).major(), QualityMajor::Bad);
+        // The reserved pattern is bad
+        assert_eq!(DataQuality(0x80).major(), QualityMajor::Bad);
+    }
+
+    #[test]
+    fn decodes_substatus_per_major_quality() {
+        assert_eq!(DataQuality(0x18).substatus(), QualitySubstatus::CommFailure);
+        assert_eq!(DataQuality(0x44).substatus(), QualitySubstatus::LastUsableValue);
+        assert_eq!(DataQuality(0xD8).substatus(), QualitySubstatus::LocalOverride);
+        assert_eq!(DataQuality(0xC0).substatus(), QualitySubstatus::NonSpecific);
+        // Code 1 is only defined for bad and uncertain
+        assert_eq!(DataQuality(0xC4).substatus(), QualitySubstatus::Other(1));
+    }
+
+    #[test]
+    fn decodes_limit_and_vendor_bits() {
+        let quality = DataQuality(0x12C1u16 as i16);
+
+        assert_eq!(quality.major(), QualityMajor::Good);
+        assert_eq!(quality.limit(), QualityLimit::Low);
+        assert_eq!(quality.vendor_bits(), 0x12);
+        assert!(quality.is_limited());
+        assert_eq!(DataQuality(0x43).limit(), QualityLimit::Constant);
+        assert_eq!(DataQuality(0xFF00u16 as i16).vendor_bits(), 0xFF);
+    }
+
+    #[test]
+    fn usable_means_good_or_uncertain() {
+        assert!(DataQuality::GOOD.is_usable());
+        assert!(DataQuality::UNCERTAIN.is_usable());
+        assert!(!DataQuality::BAD.is_usable());
+        assert!(!DataQuality(0x80).is_usable());
+    }
+
+    #[test]
+    fn displays_decoded_parts() {
+        assert_eq!(DataQuality::GOOD.to_string(), "Good");
+        assert_eq!(DataQuality(0x18).to_string(), "Bad (CommFailure)");
+        assert_eq!(DataQuality(0x56).to_string(), "Uncertain (EngineeringUnitsExceeded) [High limited]");
+    }
+}
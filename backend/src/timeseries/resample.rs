@@ -0,0 +1,158 @@
+use super::{Aggregatable, Aggregate, AggregateOptions, DataPoint, DataQuality, DataSeries, DataValue, Interpolation};
+use chrono::{DateTime, TimeDelta, Utc};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResampleMethod {
+    /// The value in effect at each grid time.
+    StepHold,
+    /// The value interpolated linearly between the surrounding points. After the last
+    /// point the last value holds.
+    Linear,
+    /// An aggregate over each interval `[t, t + interval)`, stamped at `t`.
+    Aggregate(Aggregate),
+}
+
+/// Times from `start` in steps of `interval` up to and including `end`.
+pub fn time_grid(start: DateTime<Utc>, end: DateTime<Utc>, interval: TimeDelta) -> Vec<DateTime<Utc>> {
+    let mut grid = vec![];
+    if interval <= TimeDelta::zero() {
+        return grid;
+    }
+
+    let mut t = start;
+    while t <= end {
+        grid.push(t);
+        t += interval;
+    }
+
+    grid
+}
+
+impl DataSeries {
+    /// Returns a series with one point every `interval` from `start` to `end`. Grid times
+    /// without a value, such as those before the first point, get an empty bad point, as do
+    /// intervals without recorded points for aggregates other than time-based ones.
+    pub fn resample(&self, start: DateTime<Utc>, end: DateTime<Utc>, interval: TimeDelta, method: ResampleMethod) -> DataSeries {
+        let data = match method {
+            ResampleMethod::StepHold => self.sample(start, end, interval, Interpolation::Step),
+            ResampleMethod::Linear => self.sample(start, end, interval, Interpolation::Linear),
+            ResampleMethod::Aggregate(aggregate) => self.aggregate_intervals(start, end, interval, aggregate),
+        };
+
+        DataSeries { tag: self.tag.clone(), data }
+    }
+
+    fn sample(&self, start: DateTime<Utc>, end: DateTime<Utc>, interval: TimeDelta, interpolation: Interpolation) -> Vec<DataPoint> {
+        time_grid(start, end, interval).into_iter().map(|t| {
+            let after = self.data.partition_point(|dp| dp.timestamp <= t);
+
+            self.boundary_point(after, t, interpolation).unwrap_or(DataPoint {
+                timestamp: t,
+                value: None,
+                quality: DataQuality::BAD,
+            })
+        }).collect()
+    }
+
+    fn aggregate_intervals(&self, start: DateTime<Utc>, end: DateTime<Utc>, interval: TimeDelta, aggregate: Aggregate) -> Vec<DataPoint> {
+        let mut boundaries: Vec<DateTime<Utc>> = time_grid(start, end, interval).into_iter().filter(|t| *t < end).collect();
+        boundaries.push(end);
+
+        let interpolation = self.default_interpolation();
+        let options = AggregateOptions::new(interpolation);
+
+        self.slice(&boundaries, Some(interpolation)).iter().map(|slice| {
+            // Without recorded points there is nothing to count or average
+            let value = match slice.data.is_empty() && !aggregate.follows_time() {
+                true => None,
+                false => slice.aggregate(aggregate, &options),
+            };
+
+            DataPoint {
+                timestamp: slice.start,
+                quality: if value.is_some() { DataQuality::GOOD } else { DataQuality::BAD },
+                value: value.map(DataValue::Float),
+            }
+        }).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timeseries::testing::{at, floats};
+
+    fn values(series: &DataSeries) -> Vec<(i64, Option<f64>, DataQuality)> {
+        series.data.iter()
+            .map(|dp| ((dp.timestamp - at(0)).num_seconds(), dp.value.as_ref().and_then(DataValue::as_f64), dp.quality))
+            .collect()
+    }
+
+    #[test]
+    fn time_grid_includes_the_end() {
+        assert_eq!(time_grid(at(0), at(20), TimeDelta::seconds(10)), [at(0), at(10), at(20)]);
+        assert_eq!(time_grid(at(0), at(25), TimeDelta::seconds(10)), [at(0), at(10), at(20)]);
+        assert!(time_grid(at(0), at(20), TimeDelta::zero()).is_empty());
+    }
+
+    #[test]
+    fn step_hold_is_bad_before_the_first_point_and_holds_after_the_last() {
+        let series = floats("FT", &[(10, 10.0), (20, 30.0)]);
+        let resampled = series.resample(at(0), at(40), TimeDelta::seconds(10), ResampleMethod::StepHold);
+
+        assert_eq!(values(&resampled), [
+            (0, None, DataQuality::BAD),
+            (10, Some(10.0), DataQuality::GOOD),
+            (20, Some(30.0), DataQuality::GOOD),
+            (30, Some(30.0), DataQuality::GOOD),
+            (40, Some(30.0), DataQuality::GOOD),
+        ]);
+    }
+
+    #[test]
+    fn linear_interpolates_between_points_and_holds_after_the_last() {
+        let series = floats("FT", &[(10, 10.0), (20, 30.0)]);
+        let resampled = series.resample(at(5), at(30), TimeDelta::seconds(5), ResampleMethod::Linear);
+
+        assert_eq!(values(&resampled), [
+            (5, None, DataQuality::BAD),
+            (10, Some(10.0), DataQuality::GOOD),
+            (15, Some(20.0), DataQuality::GOOD),
+            (20, Some(30.0), DataQuality::GOOD),
+            (25, Some(30.0), DataQuality::GOOD),
+            (30, Some(30.0), DataQuality::GOOD),
+        ]);
+    }
+
+    #[test]
+    fn empty_intervals_have_no_sample_aggregate() {
+        let series = floats("FT", &[(12, 10.0), (14, 20.0), (31, 40.0)]);
+        let resample = |aggregate| series.resample(at(0), at(40), TimeDelta::seconds(10), ResampleMethod::Aggregate(aggregate));
+
+        assert_eq!(values(&resample(Aggregate::Count)), [
+            (0, None, DataQuality::BAD),
+            (10, Some(2.0), DataQuality::GOOD),
+            (20, None, DataQuality::BAD),
+            (30, Some(1.0), DataQuality::GOOD),
+        ]);
+        assert_eq!(values(&resample(Aggregate::Average)), [
+            (0, None, DataQuality::BAD),
+            (10, Some(15.0), DataQuality::GOOD),
+            (20, None, DataQuality::BAD),
+            (30, Some(40.0), DataQuality::GOOD),
+        ]);
+    }
+
+    #[test]
+    fn empty_intervals_keep_time_based_aggregates() {
+        let series = floats("FT", &[(10, 10.0), (15, 20.0)]);
+        let resampled = series.resample(at(0), at(30), TimeDelta::seconds(10), ResampleMethod::Aggregate(Aggregate::TimeWeightedAverage));
+
+        // 10 rises to 20 over 5s and holds; the value in effect at 20 fills the empty interval
+        assert_eq!(values(&resampled), [
+            (0, None, DataQuality::BAD),
+            (10, Some(17.5), DataQuality::GOOD),
+            (20, Some(20.0), DataQuality::GOOD),
+        ]);
+    }
+}
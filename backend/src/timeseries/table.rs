@@ -0,0 +1,231 @@
+use super::{interpolate, DataPoint, DataQuality, DataSeries, DataValue};
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+
+/// How a column is filled at rows between its own points.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fill {
+    /// The last recorded value holds.
+    CarryForward,
+    /// Numeric values are interpolated linearly; other values carry forward.
+    Interpolate,
+}
+
+#[derive(Debug, Clone)]
+pub struct AlignOptions {
+    default_fill: Fill,
+    fills: HashMap<String, Fill>,
+    quality_columns: bool,
+}
+
+impl AlignOptions {
+    pub fn new(default_fill: Fill) -> Self {
+        Self {
+            default_fill,
+            fills: HashMap::new(),
+            quality_columns: false,
+        }
+    }
+
+    /// Overrides the fill for the column of one tag.
+    pub fn fill(mut self, tag_name: &str, fill: Fill) -> Self {
+        self.fills.insert(tag_name.to_string(), fill);
+        self
+    }
+
+    /// Adds the quality of each value to the rows.
+    pub fn quality_columns(mut self, quality_columns: bool) -> Self {
+        self.quality_columns = quality_columns;
+        self
+    }
+}
+
+impl Default for AlignOptions {
+    fn default() -> Self {
+        Self::new(Fill::CarryForward)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Column {
+    pub name: String,
+    pub uom: Option<String>,
+    /// Display format of the tag, see [`Tag::format`](super::Tag::format).
+    pub format: Option<String>,
+    /// State texts of the tag, see [`Tag::states`](super::Tag::states).
+    pub states: HashMap<i32, String>,
+    pub fill: Fill,
+}
+
+#[derive(Debug, Clone)]
+pub struct Row {
+    pub timestamp: DateTime<Utc>,
+    /// One value per column, `None` before the column's first point.
+    pub values: Vec<Option<DataValue>>,
+    /// One quality per column when quality columns were requested.
+    pub qualities: Option<Vec<Option<DataQuality>>>,
+}
+
+/// Several series aligned on the union of their timestamps, one column per series.
+#[derive(Debug, Clone)]
+pub struct WideTable {
+    pub columns: Vec<Column>,
+    pub rows: Vec<Row>,
+}
+
+impl WideTable {
+    /// Header names: `Timestamp`, then each column as `name [uom]`, followed by a
+    /// `name quality` column for each tag when the rows carry qualities.
+    pub fn headers(&self) -> Vec<String> {
+        let mut headers = vec!["Timestamp".to_string()];
+
+        headers.extend(self.columns.iter().map(|c| match &c.uom {
+            Some(uom) => format!("{} [{}]", c.name, uom),
+            None => c.name.clone(),
+        }));
+
+        if self.rows.first().is_some_and(|r| r.qualities.is_some()) {
+            headers.extend(self.columns.iter().map(|c| format!("{} quality", c.name)));
+        }
+
+        headers
+    }
+}
+
+/// Aligns several series into a wide table with one row per distinct timestamp. Columns
+/// keep the order of `series`.
+pub fn align(series: &[&DataSeries], options: &AlignOptions) -> WideTable {
+    let columns: Vec<Column> = series.iter().map(|s| Column {
+        name: s.tag.name.clone(),
+        uom: s.tag.uom.clone(),
+        format: s.tag.format.clone(),
+        states: s.tag.states.clone(),
+        fill: options.fills.get(&s.tag.name).copied().unwrap_or(options.default_fill),
+    }).collect();
+
+    let mut timestamps: Vec<DateTime<Utc>> = series.iter()
+        .flat_map(|s| s.data.iter().map(|dp| dp.timestamp))
+        .collect();
+    timestamps.sort();
+    timestamps.dedup();
+
+    // Index of the first point after the current row, per column. Rows are visited in time
+    // order so each cursor only moves forward.
+    let mut cursors = vec![0; series.len()];
+
+    let rows = timestamps.into_iter().map(|timestamp| {
+        let cells: Vec<Option<(Option<DataValue>, DataQuality)>> = series.iter().zip(&columns).zip(cursors.iter_mut())
+            .map(|((s, column), cursor)| {
+                while *cursor < s.data.len() && s.data[*cursor].timestamp <= timestamp {
+                    *cursor += 1;
+                }
+                cell(&s.data, *cursor, timestamp, column.fill)
+            })
+            .collect();
+
+        Row {
+            timestamp,
+            values: cells.iter().map(|c| c.as_ref().and_then(|(v, _)| v.clone())).collect(),
+            qualities: options.quality_columns.then(|| cells.iter().map(|c| c.as_ref().map(|(_, q)| *q)).collect()),
+        }
+    }).collect();
+
+    WideTable { columns, rows }
+}
+
+// The value of a column at `timestamp`, where `after` is the index of its first later point
+fn cell(data: &[DataPoint], after: usize, timestamp: DateTime<Utc>, fill: Fill) -> Option<(Option<DataValue>, DataQuality)> {
+    let before = &data[after.checked_sub(1)?];
+
+    let value = match (fill, data.get(after)) {
+        (Fill::Interpolate, Some(next)) if before.timestamp < timestamp => {
+            interpolate(before, next, timestamp).or_else(|| before.value.clone())
+        }
+        _ => before.value.clone(),
+    };
+
+    Some((value, before.quality))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timeseries::testing::{at, floats, point, tag};
+
+    fn column(table: &WideTable, index: usize) -> Vec<Option<DataValue>> {
+        table.rows.iter().map(|row| row.values[index].clone()).collect()
+    }
+
+    fn float(v: f64) -> Option<DataValue> {
+        Some(DataValue::Float(v))
+    }
+
+    #[test]
+    fn columns_keep_the_series_order_and_rows_cover_every_timestamp() {
+        let flow = floats("FT", &[(0, 1.0), (20, 3.0)]);
+        let level = floats("LT", &[(10, 50.0), (30, 70.0)]);
+        let table = align(&[&level, &flow], &AlignOptions::default());
+
+        assert_eq!(table.columns.iter().map(|c| c.name.as_str()).collect::<Vec<_>>(), ["LT", "FT"]);
+        assert_eq!(table.rows.iter().map(|r| r.timestamp).collect::<Vec<_>>(), [at(0), at(10), at(20), at(30)]);
+        assert_eq!(column(&table, 0), [None, float(50.0), float(50.0), float(70.0)]);
+
+        // The last row holds each column's last value past its end
+        assert_eq!(column(&table, 1), [float(1.0), float(1.0), float(3.0), float(3.0)]);
+        assert!(table.rows.iter().all(|r| r.qualities.is_none()));
+    }
+
+    #[test]
+    fn interpolate_fills_between_points_only() {
+        let flow = floats("FT", &[(0, 0.0), (10, 10.0)]);
+        let marks = floats("MARK", &[(4, 0.0), (15, 0.0)]);
+
+        let interpolated = align(&[&flow, &marks], &AlignOptions::new(Fill::Interpolate));
+        assert_eq!(column(&interpolated, 0), [float(0.0), float(4.0), float(10.0), float(10.0)]);
+
+        let carried = align(&[&flow, &marks], &AlignOptions::new(Fill::Interpolate).fill("FT", Fill::CarryForward));
+        assert_eq!(carried.columns[0].fill, Fill::CarryForward);
+        assert_eq!(column(&carried, 0), [float(0.0), float(0.0), float(10.0), float(10.0)]);
+    }
+
+    #[test]
+    fn interpolate_carries_text_forward() {
+        let mode = DataSeries {
+            tag: tag("MODE"),
+            data: vec![point(0, DataValue::Text("auto".into())), point(10, DataValue::Text("manual".into()))],
+        };
+        let marks = floats("MARK", &[(5, 0.0)]);
+        let table = align(&[&mode, &marks], &AlignOptions::new(Fill::Interpolate));
+
+        assert_eq!(column(&table, 0)[1], Some(DataValue::Text("auto".into())));
+    }
+
+    #[test]
+    fn quality_columns_follow_the_value_in_effect() {
+        let mut flow = floats("FT", &[(0, 1.0), (10, 2.0)]);
+        flow.data[0].quality = DataQuality::UNCERTAIN;
+        let level = floats("LT", &[(5, 50.0)]);
+
+        let table = align(&[&flow, &level], &AlignOptions::new(Fill::Interpolate).quality_columns(true));
+        let qualities: Vec<_> = table.rows.iter().map(|r| r.qualities.clone().unwrap()).collect();
+
+        assert_eq!(qualities, [
+            vec![Some(DataQuality::UNCERTAIN), None],
+            vec![Some(DataQuality::UNCERTAIN), Some(DataQuality::GOOD)],
+            vec![Some(DataQuality::GOOD), Some(DataQuality::GOOD)],
+        ]);
+    }
+
+    #[test]
+    fn headers_name_units_and_quality_columns() {
+        let mut flow = floats("FT", &[(0, 1.0)]);
+        flow.tag.uom = Some("m3/h".to_string());
+        let level = floats("LT", &[(0, 50.0)]);
+
+        let table = align(&[&flow, &level], &AlignOptions::default());
+        assert_eq!(table.headers(), ["Timestamp", "FT [m3/h]", "LT"]);
+
+        let table = align(&[&flow, &level], &AlignOptions::default().quality_columns(true));
+        assert_eq!(table.headers(), ["Timestamp", "FT [m3/h]", "LT", "FT quality", "LT quality"]);
+    }
+}
@@ -0,0 +1,43 @@
+use super::{DataPoint, DataQuality, DataSeries, DataValue, Tag};
+use chrono::{DateTime, TimeZone, Utc};
+use std::collections::HashMap;
+
+// Fixtures shared by the unit tests of the timeseries modules
+
+/// The time `seconds` after 2025-01-01T00:00:00Z.
+pub(crate) fn at(seconds: i64) -> DateTime<Utc> {
+    Utc.timestamp_opt(1_735_689_600 + seconds, 0).unwrap()
+}
+
+/// A tag with just a name.
+pub(crate) fn tag(name: &str) -> Tag {
+    Tag {
+        name: name.to_string(),
+        description: None,
+        format: None,
+        uom: None,
+        states: HashMap::new(),
+        fields: HashMap::new(),
+    }
+}
+
+/// A good point at `at(seconds)`.
+pub(crate) fn point(seconds: i64, value: DataValue) -> DataPoint {
+    DataPoint { timestamp: at(seconds), value: Some(value), quality: DataQuality::GOOD }
+}
+
+/// A series of good float points, given as `(seconds, value)`.
+pub(crate) fn floats(name: &str, points: &[(i64, f64)]) -> DataSeries {
+    DataSeries {
+        tag: tag(name),
+        data: points.iter().map(|&(seconds, value)| point(seconds, DataValue::Float(value))).collect(),
+    }
+}
+
+/// A series of good integer points, given as `(seconds, value)`.
+pub(crate) fn integers(name: &str, points: &[(i64, i32)]) -> DataSeries {
+    DataSeries {
+        tag: tag(name),
+        data: points.iter().map(|&(seconds, value)| point(seconds, DataValue::Integer(value))).collect(),
+    }
+}
@@ -0,0 +1,330 @@
+use super::{BoundaryPolicy, DataPoint, DataQuality, DataSeries, DataValue, QualityFilter};
+use chrono::{DateTime, TimeDelta, Utc};
+
+/// Interprets a counter tag, such as `131-FQ-001.PV`, that only ever counts up but may be
+/// reset, e.g. at batch start, or wrap around at a maximum value.
+///
+/// Every decrease between two consecutive points is classified:
+/// - no larger than `reset_threshold`, or than the `reset_fraction` of the previous reading:
+///   measurement noise, counted as the small negative change it is, so the total stays right
+///   when the counter creeps back up;
+/// - from the top `rollover_band` of the range to the bottom of it, when a rollover value is
+///   set: a wrap-around, counting up to the maximum and on from zero;
+/// - anything else: a reset, counting from `reset_value` to the new value.
+#[derive(Debug, Clone, Copy)]
+pub struct Totalizer {
+    rollover: Option<f64>,
+    rollover_band: f64,
+    reset_threshold: f64,
+    reset_fraction: f64,
+    reset_value: f64,
+    quality: QualityFilter,
+}
+
+impl Default for Totalizer {
+    fn default() -> Self {
+        Self {
+            rollover: None,
+            rollover_band: 0.1,
+            reset_threshold: 0.0,
+            reset_fraction: 0.5,
+            reset_value: 0.0,
+            quality: QualityFilter::Usable,
+        }
+    }
+}
+
+impl Totalizer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the value at which the counter wraps back to zero.
+    pub fn rollover(mut self, max: f64) -> Self {
+        self.rollover = Some(max);
+        self
+    }
+
+    /// Sets the fraction of the range, at its top and bottom, in which a drop is taken to be a
+    /// rollover. Defaults to 0.1.
+    pub fn rollover_band(mut self, band: f64) -> Self {
+        self.rollover_band = band.clamp(0.0, 0.5);
+        self
+    }
+
+    /// Sets the largest decrease that is treated as noise rather than a reset. Defaults to 0.
+    pub fn reset_threshold(mut self, threshold: f64) -> Self {
+        self.reset_threshold = threshold.max(0.0);
+        self
+    }
+
+    /// Sets the fraction of the previous reading that a decrease must exceed to be a reset
+    /// rather than noise. Defaults to 0.5, i.e. a reset loses at least half the count.
+    pub fn reset_fraction(mut self, fraction: f64) -> Self {
+        self.reset_fraction = fraction.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Sets the value the counter restarts from after a reset. Defaults to 0.
+    pub fn reset_value(mut self, value: f64) -> Self {
+        self.reset_value = value;
+        self
+    }
+
+    pub fn quality(mut self, quality: QualityFilter) -> Self {
+        self.quality = quality;
+        self
+    }
+
+    /// The true increase of a counter from one reading to the next.
+    pub fn increment(&self, previous: f64, next: f64) -> f64 {
+        let drop = previous - next;
+
+        if drop <= self.reset_threshold || drop <= self.reset_fraction * previous.abs() {
+            return next - previous;
+        }
+
+        match self.rollover {
+            Some(max) if previous >= max * (1.0 - self.rollover_band) && next <= max * self.rollover_band => {
+                (max - previous) + next
+            }
+            _ => (next - self.reset_value).max(0.0),
+        }
+    }
+
+    /// The accumulated increase of the counter from `start` to `end`. The counter readings at
+    /// the window bounds are interpolated between the neighbouring points, unless a reset or
+    /// rollover lies between them, in which case the earlier reading holds.
+    pub fn total(&self, series: &DataSeries, start: DateTime<Utc>, end: DateTime<Utc>) -> Option<f64> {
+        let first = series.data.partition_point(|dp| dp.timestamp <= start);
+        let last = series.data.partition_point(|dp| dp.timestamp < end);
+
+        let readings = std::iter::once(self.reading_at(series, start))
+            .chain(series.data[first..last.max(first)].iter().map(|dp| self.reading(dp)))
+            .chain(std::iter::once(self.reading_at(series, end)))
+            .flatten();
+
+        let mut total = None;
+        let mut previous: Option<f64> = None;
+
+        for reading in readings {
+            if let Some(previous) = previous {
+                *total.get_or_insert(0.0) += self.increment(previous, reading);
+            }
+            previous = Some(reading);
+        }
+
+        total
+    }
+
+    /// Derives the rate of the counter, in counts per `per`. Each point holds the average rate
+    /// until the next reading, so integrating it with step interpolation gives the total.
+    pub fn rate(&self, series: &DataSeries, per: TimeDelta) -> DataSeries {
+        let readings: Vec<(&DataPoint, f64)> = series.data.iter()
+            .filter_map(|dp| self.reading(dp).map(|value| (dp, value)))
+            .collect();
+
+        let data = readings.windows(2).map(|pair| {
+            let ((from, previous), (to, next)) = (pair[0], pair[1]);
+            let periods = (to.timestamp - from.timestamp).as_seconds_f64() / per.as_seconds_f64();
+
+            DataPoint {
+                timestamp: from.timestamp,
+                value: (periods > 0.0).then(|| DataValue::Float(self.increment(previous, next) / periods)),
+                quality: if from.quality.is_good() && to.quality.is_good() { DataQuality::GOOD } else { DataQuality::UNCERTAIN },
+            }
+        }).collect();
+
+        let mut tag = series.tag.clone();
+        tag.uom = tag.uom.map(|uom| format!("{}/{}", uom, unit_label(per)));
+        tag.states.clear();
+
+        DataSeries { tag, data }
+    }
+
+    /// The periods in `start..end` not covered by usable readings at most `max_gap` apart.
+    pub fn gaps(&self, series: &DataSeries, start: DateTime<Utc>, end: DateTime<Utc>, max_gap: TimeDelta) -> Vec<(DateTime<Utc>, DateTime<Utc>)> {
+        // The readings around the window count towards covering its edges
+        let first = series.data.partition_point(|dp| dp.timestamp <= start).saturating_sub(1);
+        let last = (series.data.partition_point(|dp| dp.timestamp < end) + 1).min(series.data.len());
+
+        let mut gaps = vec![];
+        let mut covered_to = start;
+        let mut previous: Option<DateTime<Utc>> = None;
+
+        for point in series.data[first..last].iter().filter(|dp| self.reading(dp).is_some()) {
+            if let Some(previous) = previous && point.timestamp - previous <= max_gap {
+                if previous.min(end) > covered_to {
+                    gaps.push((covered_to, previous.min(end)));
+                }
+                covered_to = covered_to.max(point.timestamp);
+            }
+            previous = Some(point.timestamp);
+        }
+
+        if covered_to < end {
+            gaps.push((covered_to, end));
+        }
+
+        gaps
+    }
+
+    fn reading(&self, point: &DataPoint) -> Option<f64> {
+        if !self.quality.accepts(point.quality) {
+            return None;
+        }
+
+        point.value.as_ref()?.as_f64()
+    }
+
+    fn reading_at(&self, series: &DataSeries, timestamp: DateTime<Utc>) -> Option<f64> {
+        let before = self.reading(series.value_at_or_before(timestamp, BoundaryPolicy::None)?)?;
+        let interpolated = series.interpolated_value_at(timestamp, BoundaryPolicy::None)
+            .and_then(|dp| dp.value?.as_f64());
+
+        // Interpolating across a reset would invent readings the counter never had
+        match interpolated {
+            Some(value) if value >= before => Some(value),
+            _ => Some(before),
+        }
+    }
+}
+
+fn unit_label(per: TimeDelta) -> String {
+    match per.num_seconds() {
+        1 => "s".to_string(),
+        60 => "min".to_string(),
+        3600 => "h".to_string(),
+        86400 => "d".to_string(),
+        seconds => format!("{}s", seconds),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timeseries::testing::at;
+    use crate::timeseries::Tag;
+    use std::collections::HashMap;
+
+    fn counter(points: &[(i64, f64, DataQuality)]) -> DataSeries {
+        DataSeries {
+            tag: Tag {
+                name: "131-FQ-001.PV".to_string(),
+                description: None,
+                format: None,
+                uom: Some("m3".to_string()),
+                states: HashMap::new(),
+                fields: HashMap::new(),
+            },
+            data: points.iter()
+                .map(|&(seconds, value, quality)| DataPoint { timestamp: at(seconds), value: Some(DataValue::Float(value)), quality })
+                .collect(),
+        }
+    }
+
+    fn good(points: &[(i64, f64)]) -> DataSeries {
+        counter(&points.iter().map(|&(seconds, value)| (seconds, value, DataQuality::GOOD)).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn increment_classifies_decreases() {
+        let totalizer = Totalizer::new().rollover(1000.0).reset_threshold(1.0);
+
+        assert_eq!(totalizer.increment(100.0, 150.0), 50.0);
+        // Noise within the threshold counts as the small negative change it is
+        assert_eq!(totalizer.increment(100.0, 99.5), -0.5);
+        // From the top band to the bottom band is a rollover
+        assert_eq!(totalizer.increment(990.0, 10.0), 20.0);
+        // Anything else is a reset, counting from zero
+        assert_eq!(totalizer.increment(500.0, 10.0), 10.0);
+    }
+
+    #[test]
+    fn increment_without_rollover_treats_large_drops_as_resets() {
+        let totalizer = Totalizer::new();
+
+        assert_eq!(totalizer.increment(990.0, 10.0), 10.0);
+        assert_eq!(totalizer.increment(100.0, 99.5), -0.5);
+        assert_eq!(totalizer.increment(100.0, 40.0), 40.0);
+
+        let strict = totalizer.reset_fraction(0.0);
+        assert_eq!(strict.increment(100.0, 99.5), 99.5);
+    }
+
+    #[test]
+    fn small_noise_stays_small() {
+        let series = good(&[(0, 1000.0), (10, 999.99), (20, 1001.0)]);
+        let total = Totalizer::new().total(&series, at(0), at(20)).unwrap();
+
+        assert!((total - 1.0).abs() < 1e-9, "{}", total);
+    }
+
+    #[test]
+    fn increment_after_reset_counts_from_reset_value() {
+        let totalizer = Totalizer::new().reset_value(5.0);
+
+        assert_eq!(totalizer.increment(500.0, 12.0), 7.0);
+        // A counter that restarts below its reset value adds nothing
+        assert_eq!(totalizer.increment(500.0, 2.0), 0.0);
+    }
+
+    #[test]
+    fn total_interpolates_bounds_and_counts_resets() {
+        let series = good(&[(0, 100.0), (10, 200.0), (20, 5.0), (30, 105.0)]);
+
+        // 150 -> 200, reset to 5, then 5 -> 55. The reading at 25s lies between 5 and 105.
+        assert_eq!(Totalizer::new().total(&series, at(5), at(25)), Some(105.0));
+    }
+
+    #[test]
+    fn total_holds_the_earlier_reading_across_a_reset() {
+        let series = good(&[(0, 100.0), (10, 200.0), (20, 5.0)]);
+
+        // Interpolating 200 -> 5 at 15s would give 102.5; the counter still read 200 then
+        assert_eq!(Totalizer::new().total(&series, at(0), at(15)), Some(100.0));
+    }
+
+    #[test]
+    fn total_counts_through_rollover() {
+        let series = good(&[(0, 950.0), (10, 995.0), (20, 15.0), (30, 40.0)]);
+
+        assert_eq!(Totalizer::new().rollover(1000.0).total(&series, at(0), at(30)), Some(90.0));
+    }
+
+    #[test]
+    fn total_skips_bad_readings() {
+        let series = counter(&[
+            (0, 100.0, DataQuality::GOOD),
+            (10, 0.0, DataQuality::BAD),
+            (20, 130.0, DataQuality::GOOD),
+        ]);
+
+        assert_eq!(Totalizer::new().total(&series, at(0), at(20)), Some(30.0));
+    }
+
+    #[test]
+    fn total_without_readings_is_none() {
+        let series = good(&[(100, 1.0)]);
+
+        assert_eq!(Totalizer::new().total(&series, at(0), at(50)), None);
+    }
+
+    #[test]
+    fn rate_uses_increments() {
+        let series = good(&[(0, 990.0), (60, 10.0), (120, 70.0)]);
+        let rate = Totalizer::new().rollover(1000.0).rate(&series, TimeDelta::minutes(1));
+
+        let values: Vec<Option<f64>> = rate.data.iter().map(|dp| dp.value.as_ref().and_then(DataValue::as_f64)).collect();
+        assert_eq!(values, [Some(20.0), Some(60.0)]);
+        assert_eq!(rate.tag.uom.as_deref(), Some("m3/min"));
+    }
+
+    #[test]
+    fn gaps_reports_uncovered_periods() {
+        let series = good(&[(0, 1.0), (10, 2.0), (60, 3.0), (70, 4.0)]);
+        let gaps = Totalizer::new().gaps(&series, at(0), at(100), TimeDelta::seconds(15));
+
+        assert_eq!(gaps, [(at(10), at(60)), (at(70), at(100))]);
+    }
+}